@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::process::ExitCode;
+
+use common::script::{PrintOptions, Script, ScriptError, TickStyle};
+use common::tas::to_time;
+
+const USAGE: &str = "Usage: witas <command> [options] <files>...
+
+Commands:
+    check   Parse the scripts and show their likely mistakes
+    fmt     Rewrite the scripts in the canonical format
+    abs     Rewrite the scripts with absolute ticks
+    rel     Rewrite the scripts with relative ticks
+    info    Show the length, the number of lines and the tools of the scripts
+
+Options of fmt, abs and rel:
+    --check     Only list the scripts that would change, and fail if there are any
+    --align     Line up the fields of consecutive lines";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((command, args)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let (flags, files): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    if files.is_empty() {
+        eprintln!("No script given\n\n{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let rewrite = |ticks| {
+        let mut options = PrintOptions {
+            ticks,
+            ..Default::default()
+        };
+        let mut check_only = false;
+        for flag in &flags {
+            match flag.as_str() {
+                "--check" => check_only = true,
+                "--align" => options.align = true,
+                _ => {
+                    eprintln!("Unknown option `{flag}`\n\n{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        for_each_script(&files, |file, script| {
+            rewrite_script(file, script, &options, check_only)
+        })
+    };
+
+    match command.as_str() {
+        "check" | "info" if !flags.is_empty() => {
+            eprintln!("`{command}` has no options\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+        "check" => for_each_script(&files, check),
+        "fmt" => rewrite(TickStyle::AsWritten),
+        "abs" => rewrite(TickStyle::Absolute),
+        "rel" => rewrite(TickStyle::Relative),
+        "info" => for_each_script(&files, |file, script| {
+            println!("{file}:\n{}", info(script));
+            true
+        }),
+        _ => {
+            eprintln!("Unknown command `{command}`\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints an error or a warning, with its hint on the following line
+fn report(kind: &str, error: &ScriptError, category: Option<&str>) {
+    match category {
+        Some(category) => println!("{kind}: {error} [{category}]"),
+        None => println!("{kind}: {error}"),
+    }
+    if let Some(hint) = &error.hint {
+        println!("    hint: {hint}");
+    }
+}
+
+/// Parses each file and runs `action` on the scripts that parse.
+/// Fails if a script does not parse, or if `action` fails for one of them.
+fn for_each_script(files: &[&String], mut action: impl FnMut(&str, &Script) -> bool) -> ExitCode {
+    let mut failed = false;
+
+    for file in files {
+        match Script::from_file(Path::new(file)) {
+            Ok(script) => failed |= !action(file, &script),
+            Err(errors) => {
+                failed = true;
+                for error in &errors {
+                    report("error", error, None);
+                }
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Prints the lint warnings of a script. Warnings do not make the check fail.
+fn check(_file: &str, script: &Script) -> bool {
+    for lint in script.lint() {
+        report("warning", &lint.warning, Some(lint.category.name()));
+    }
+
+    true
+}
+
+/// Prints the script with `options` to its file, or only tells if it would change
+fn rewrite_script(file: &str, script: &Script, options: &PrintOptions, check_only: bool) -> bool {
+    let printed = script.print(options);
+    if printed == script.src {
+        return true;
+    }
+
+    if check_only {
+        println!("{file} would be rewritten");
+        return false;
+    }
+
+    match std::fs::write(file, printed) {
+        Ok(()) => {
+            println!("Rewrote {file}");
+            true
+        }
+        Err(err) => {
+            eprintln!("Cannot write {file}: {err}");
+            false
+        }
+    }
+}
+
+/// Summary of a script: its length, number of lines and the tools it uses
+fn info(script: &Script) -> String {
+    let length = script.lines.last().map_or(0, |line| line.absolute_tick);
+    // Lines of repeat blocks and macros are written once, but played several times
+    let written: HashSet<_> = script
+        .lines
+        .iter()
+        .filter(|line| !line.generated)
+        .map(|line| (&line.file, line.span.start))
+        .collect();
+
+    let mut tools: BTreeMap<&str, usize> = BTreeMap::new();
+    for tool in script
+        .lines
+        .iter()
+        .filter_map(|line| line.tools.as_ref())
+        .flatten()
+    {
+        *tools.entry(tool.name()).or_default() += 1;
+    }
+
+    let mut info = format!("    length: {length} ticks ({})", to_time(length));
+    if script.lines.iter().any(|line| !line.waits.is_empty()) {
+        info.push_str(", plus the time spent waiting");
+    }
+    info.push_str(&format!(
+        "\n    lines: {} played, {} written",
+        script.lines.len(),
+        written.len()
+    ));
+
+    if tools.is_empty() {
+        info.push_str("\n    tools: none");
+    } else {
+        let tools: Vec<_> = tools
+            .iter()
+            .map(|(name, count)| format!("{name} ({count})"))
+            .collect();
+        info.push_str(&format!("\n    tools: {}", tools.join(", ")));
+    }
+
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use common::script::Script;
+    use common::tas::to_time;
+
+    use super::info;
+
+    #[test]
+    fn test_info() {
+        let src = "version 1
+start now
+1>U||setpos 1 2 3 0 0
+repeat 2 {
+    +10>||assert unlocked
+}
++5..+9>|linear(100, 0)|lookat 1 2 3
+";
+        let script = Script::try_from(src.to_string()).unwrap();
+        assert_eq!(
+            info(&script),
+            format!(
+                "    length: 31 ticks ({})
+    lines: 9 played, 3 written
+    tools: assert (2), lookat (1), setpos (1)",
+                to_time(31)
+            )
+        );
+    }
+}
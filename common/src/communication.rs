@@ -5,30 +5,86 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
-use crate::tas::{PlaybackState, TraceDrawOptions};
+use crate::script::{Label, LintWarning, Metadata, ScriptError, SettingCheck};
+use crate::tas::{AssertionFailure, PlaybackState, TraceDrawOptions};
 
 #[derive(Serialize, Deserialize)]
 pub enum ControllerToTasMessage {
     PlayFile(String),
     Stop,
+    /// Reparses the script that is playing, to keep playing its new version
+    Reload,
     SkipTo(u32),
     PauseAt(u32),
+    /// Skip until the line following the label has played
+    SkipToLabel(String),
+    /// Pause after the line following the label has played
+    PauseAtLabel(String),
     AdvanceFrame,
     TeleportToTick(u32),
     TraceOptions(TraceDrawOptions),
+    /// Whether to pause the playback when an assertion fails
+    PauseOnAssertionFailure(bool),
+    /// Whether to hold every script to the rules of the `legit` directive
+    LegitMode(bool),
+    /// Records the inputs of the player, to write them as a script to the given file
+    StartRecording(String),
+    /// While paused, hands the game to the player and records their inputs, to replace
+    /// the lines after the current tick. They are sent back as `RecordedLines`, and
+    /// also written to the script file if set. Ended by `StopRecording`.
+    TakeOver {
+        write_to_script: bool,
+    },
+    StopRecording,
+    /// Saves the game and the playback on the current tick to the given slot
+    SaveState(u32),
+    /// Goes back to the state saved in the given slot, and pauses there
+    LoadState(u32),
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum TasToControllerMessage {
     PlaybackState(PlaybackState),
     CurrentTick(u32),
-    ParseErrors(Vec<String>),
+    ParseErrors(Vec<ScriptError>),
+    /// Likely mistakes in the script, sent after it is parsed
+    LintWarnings(Vec<LintWarning>),
+    /// The labels of the script, sent after it is parsed
+    Labels(Vec<Label>),
+    /// The metadata of the script that is starting, sent before it plays
+    ScriptMetadata(Metadata),
+    /// Differences between the settings of the script and the game.
+    /// The script does not play if any of them is an error.
+    SettingChecks(Vec<SettingCheck>),
     CarlInfo {
         pos: (f32, f32, f32),
         ang: (f32, f32),
     },
     /// Indicates that a puzzle unlocked on the given tick
     PuzzleUnlock(u32),
+    AssertionFailed(AssertionFailure),
+    /// The lines recorded after taking over the playback, to append after the last line played
+    RecordedLines(String),
+    /// Why the playback could not be taken over, or its recording not appended
+    TakeoverError(String),
+    /// The script was reparsed while playing. If it plays differently before the
+    /// current tick, the old version keeps playing and the first such tick is given.
+    Reloaded {
+        diverged_at: Option<u32>,
+    },
+    /// A savestate was saved. `not_captured` is the state of the game it does
+    /// not hold, that may not be as it was on the saved tick once loaded.
+    StateSaved {
+        slot: u32,
+        tick: u32,
+        not_captured: Vec<String>,
+    },
+    StateLoaded {
+        slot: u32,
+        tick: u32,
+    },
+    /// Why a savestate could not be saved or loaded
+    SavestateError(String),
 }
 
 /// Starts a server and listens for connections.
@@ -1,4 +1,6 @@
 pub mod communication;
+pub mod playback;
+pub mod recording;
 pub mod script;
 pub mod tas;
 pub mod witness;
@@ -0,0 +1,1162 @@
+use std::sync::mpsc::Sender;
+
+use tracing::{error, info};
+
+use crate::communication::TasToControllerMessage;
+use crate::script::{
+    Assertion, Input, KeyAction, MouseButton, PrintOptions, Script, ScriptLine, Statement, Tool,
+    Wait,
+};
+use crate::tas::{AssertionFailure, PlaybackState};
+use crate::witness::windows_types::VirtualKeyCode;
+use crate::witness::witness_types::{InteractionStatus, Vec2, Vec3};
+
+/// A set of virtual key codes
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KeySet([u64; 4]);
+
+impl KeySet {
+    pub fn insert(&mut self, key: VirtualKeyCode) {
+        let code = key as usize;
+        self.0[code / 64] |= 1 << (code % 64);
+    }
+
+    pub fn remove(&mut self, key: VirtualKeyCode) {
+        let code = key as usize;
+        self.0[code / 64] &= !(1 << (code % 64));
+    }
+
+    fn contains_code(&self, code: u32) -> bool {
+        self.0[code as usize / 64] & (1 << (code % 64)) != 0
+    }
+
+    /// The codes of the keys that are in this set but not in `other`
+    pub fn difference(&self, other: &KeySet) -> impl Iterator<Item = u32> {
+        let (keys, other) = (*self, *other);
+        (0..256).filter(move |code| keys.contains_code(*code) && !other.contains_code(*code))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HalfControllerState {
+    /// The keyboard keys held down
+    pub keys: KeySet,
+
+    pub mouse_pos: (i32, i32),
+    pub left_click: bool,
+    pub right_click: bool,
+}
+
+impl HalfControllerState {
+    fn press(&mut self, input: Input) {
+        match input {
+            Input::Keyboard(code) => self.keys.insert(code),
+            Input::Mouse(MouseButton::Left) => self.left_click = true,
+            Input::Mouse(MouseButton::Right) => self.right_click = true,
+        }
+    }
+
+    fn release(&mut self, input: Input) {
+        match input {
+            Input::Keyboard(code) => self.keys.remove(code),
+            Input::Mouse(MouseButton::Left) => self.left_click = false,
+            Input::Mouse(MouseButton::Right) => self.right_click = false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ControllerState {
+    pub current: HalfControllerState,
+    pub previous: HalfControllerState,
+}
+
+/// What the playback reads from the game and changes in it
+pub trait GameInterface {
+    fn player_pos(&self) -> Vec3;
+    fn set_player_pos(&mut self, pos: Vec3);
+    fn player_ang(&self) -> Vec2;
+    fn set_player_ang(&mut self, ang: Vec2);
+    /// `None` if the game is in a status we don't know
+    fn interaction_status(&self) -> Option<InteractionStatus>;
+    /// Seeds the random number generator the same way for every playback
+    fn reset_rng(&mut self);
+}
+
+/// Where the inputs recorded after taking over a paused playback go in its script
+#[derive(Debug, Clone)]
+pub struct Takeover {
+    /// The inputs played by the script on the tick it was taken over on
+    pub controller: HalfControllerState,
+    /// The tick it was taken over on, as an absolute tick of the script
+    tick: u32,
+    /// Byte offset in the source of the script, after the last line played
+    cut: usize,
+    /// The statements after the cut that do not play inputs, to keep after the recording
+    kept: Vec<String>,
+}
+
+impl Takeover {
+    /// Writes the lines recorded from the takeover, to put after the last line played.
+    /// The first one gets an absolute tick, so that it does not depend on the lines before.
+    pub fn text(&self, mut lines: Vec<ScriptLine>) -> String {
+        if let Some(first) = lines.first_mut() {
+            first.relative = false;
+            first.tick = self.tick + first.absolute_tick;
+        }
+
+        Script::from_lines(&lines).print_body(&PrintOptions::default())
+    }
+
+    /// The source of the script with `text` replacing the lines after the last one played.
+    /// The macro definitions and the includes after them are kept, after `text`.
+    pub fn append(&self, src: &str, text: &str) -> String {
+        let mut appended = src[..self.cut].to_string();
+        if !appended.ends_with('\n') {
+            appended.push('\n');
+        }
+        appended.push_str(text);
+        for statement in &self.kept {
+            appended.push('\n');
+            appended.push_str(statement);
+            appended.push('\n');
+        }
+        appended
+    }
+}
+
+/// The bookkeeping of a playback on a tick, to go back to it along with the game
+#[derive(Debug, Clone)]
+pub struct PlaybackSnapshot {
+    state: PlaybackState,
+    current_tick: u32,
+    next_line: usize,
+    waits_done: usize,
+    wait_start: u32,
+    tick_offset: u32,
+    last_puzzle_unlock: Option<u32>,
+    last_line_tick: u32,
+    line_ticks: Vec<u32>,
+    releases: Vec<(u32, Input)>,
+    script: Option<Script>,
+    controller: HalfControllerState,
+}
+
+impl PlaybackSnapshot {
+    pub fn tick(&self) -> u32 {
+        self.current_tick
+    }
+}
+
+/// Plays a script, and gives the inputs to send to the game on each tick.
+/// The ticks are counted from the start of the playback.
+pub struct Playback {
+    send: Sender<TasToControllerMessage>,
+    state: PlaybackState,
+
+    current_tick: u32,
+    skipto_tick: u32,
+    pauseat_tick: u32,
+    // When set, the labels are used instead of the ticks
+    skipto_label: Option<String>,
+    pauseat_label: Option<String>,
+
+    next_line: usize,
+
+    // Waits of the next line
+    waits_done: usize,
+    /// Only the events from this tick count for the current wait
+    wait_start: u32,
+    /// How many ticks the script was delayed by the waits
+    tick_offset: u32,
+    last_puzzle_unlock: Option<u32>,
+    /// Tick the previous line was played on
+    last_line_tick: u32,
+    /// The tick each line played so far was played on
+    line_ticks: Vec<u32>,
+    /// The inputs to release, with the tick to release them on
+    releases: Vec<(u32, Input)>,
+
+    script: Option<Script>,
+
+    controller: ControllerState,
+    /// The inputs of a restored snapshot, played on the next tick. Until then,
+    /// the game still holds the inputs played before the restore.
+    restored: Option<HalfControllerState>,
+
+    pub pause_on_assertion_failure: bool,
+    /// Play every script as if it had the `legit` directive
+    pub legit_mode: bool,
+}
+
+impl Playback {
+    /// Creates a stopped playback, that reports to the controller through `send`
+    pub fn new(send: Sender<TasToControllerMessage>) -> Self {
+        Self {
+            send,
+            state: PlaybackState::Stopped,
+            current_tick: 0,
+            skipto_tick: 0,
+            pauseat_tick: 0,
+            skipto_label: None,
+            pauseat_label: None,
+            next_line: 0,
+            waits_done: 0,
+            wait_start: 0,
+            tick_offset: 0,
+            last_puzzle_unlock: None,
+            last_line_tick: 0,
+            line_ticks: vec![],
+            releases: vec![],
+            script: None,
+            controller: Default::default(),
+            restored: None,
+            pause_on_assertion_failure: false,
+            legit_mode: false,
+        }
+    }
+
+    /// Starts playing `script`, its first tick is tick 0
+    pub fn start(&mut self, script: Script) {
+        self.script = Some(script);
+        self.controller = Default::default();
+        self.restored = None;
+        self.current_tick = 0;
+        self.next_line = 0;
+        self.waits_done = 0;
+        self.wait_start = 0;
+        self.tick_offset = 0;
+        self.last_puzzle_unlock = None;
+        self.last_line_tick = 0;
+        self.line_ticks.clear();
+        self.releases.clear();
+        self.state = PlaybackState::Playing;
+    }
+
+    pub fn stop(&mut self) {
+        if self.state != PlaybackState::Stopped {
+            self.state = PlaybackState::Stopped;
+
+            let ticks = self.current_tick;
+            info!("Stopped TAS after {ticks} ticks.")
+        }
+    }
+
+    /// Pauses the playback. Each tick played while paused is a single frame.
+    pub fn pause(&mut self) {
+        self.state = PlaybackState::Paused;
+    }
+
+    /// Continues a paused playback
+    pub fn resume(&mut self) {
+        if self.state == PlaybackState::Paused {
+            self.state = PlaybackState::Playing;
+        }
+    }
+
+    /// Stops the paused playback, to hand the game to the player. The recorded inputs
+    /// replace the lines after the last one played, which must be written at the top
+    /// level of the script file and be done playing.
+    pub fn take_over(&mut self) -> Result<Takeover, String> {
+        let tick = self.current_tick;
+        let script = match &self.script {
+            Some(script) if self.state == PlaybackState::Paused => script,
+            _ => return Err("Only a paused playback can be taken over".to_string()),
+        };
+
+        let holding = self.releases.iter().any(|(release, _)| *release > tick + 1);
+        if holding
+            || script
+                .lines
+                .get(self.next_line)
+                .is_some_and(|line| line.generated)
+        {
+            return Err(format!(
+                "An interpolation or a held key is still playing on tick {tick}, pause after it"
+            ));
+        }
+        let Some(last) = script.lines[..self.next_line]
+            .iter()
+            .rev()
+            .find(|line| !line.generated)
+        else {
+            return Err("No line of the script was played yet".to_string());
+        };
+
+        let top_level = script
+            .statements
+            .iter()
+            .position(
+                |statement| matches!(statement, Statement::Line(line) if line.span == last.span),
+            )
+            .filter(|_| last.file == script.path);
+        let Some(index) = top_level else {
+            return Err(format!(
+                "The last line played before tick {tick} is in a repeat block, a macro or an \
+                included file, the recording cannot follow it"
+            ));
+        };
+
+        // The spans count chars
+        let byte = |index: usize| {
+            script
+                .src
+                .char_indices()
+                .nth(index)
+                .map_or(script.src.len(), |(index, _)| index)
+        };
+        // Cut at the end of the text line, to keep its comment
+        let end = byte(last.span.end);
+        let cut = script.src[end..]
+            .find('\n')
+            .map_or(script.src.len(), |newline| end + newline + 1);
+
+        // Macros can be used before their definition. The labels would name no line,
+        // they are left as comments.
+        let kept = script.statements[index + 1..]
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Macro { span, .. } | Statement::Include { span, .. } => {
+                    Some(script.src[byte(span.start)..byte(span.end)].to_string())
+                }
+                Statement::Label { span, .. } => Some(format!(
+                    "// {}",
+                    &script.src[byte(span.start)..byte(span.end)]
+                )),
+                _ => None,
+            })
+            .collect();
+
+        let takeover = Takeover {
+            controller: self.controller.current,
+            tick: tick - self.tick_offset,
+            cut,
+            kept,
+        };
+        self.stop();
+        Ok(takeover)
+    }
+
+    /// Replaces the script by a new version of it, if the new one plays the same lines
+    /// up to the current tick. Otherwise, returns the first tick it plays differently on.
+    pub fn reload(&mut self, script: Script) -> Result<(), u32> {
+        let Some(old) = &self.script else {
+            self.script = Some(script);
+            return Ok(());
+        };
+        if script.start != old.start || script.metadata != old.metadata {
+            return Err(0);
+        }
+
+        // What the lines play, regardless of how they are written
+        let plays_like = |line: &ScriptLine, old: &ScriptLine| {
+            line.absolute_tick == old.absolute_tick
+                && line.keys == old.keys
+                && line.mouse == old.mouse
+                && line.tools == old.tools
+                && line.waits == old.waits
+        };
+        for (index, played) in old.lines[..self.next_line].iter().enumerate() {
+            let played_tick = self.line_ticks[index];
+            match script.lines.get(index) {
+                Some(line) if plays_like(line, played) => {}
+                // A line added before the played one may play earlier
+                Some(line) => {
+                    let offset = played_tick - played.absolute_tick;
+                    return Err(played_tick.min(line.absolute_tick + offset));
+                }
+                None => return Err(played_tick),
+            }
+        }
+
+        // The next line must not be due already, nor wait for other events
+        if let Some(next) = script.lines.get(self.next_line) {
+            let waits_done = old
+                .lines
+                .get(self.next_line)
+                .map_or(&[][..], |line| &line.waits[..self.waits_done]);
+            if !next.waits.starts_with(waits_done) {
+                return Err(self.current_tick);
+            }
+
+            let due = next.absolute_tick + self.tick_offset;
+            if self.waits_done == next.waits.len() && due <= self.current_tick {
+                return Err(due.max(self.last_line_tick));
+            }
+        }
+
+        self.script = Some(script);
+        Ok(())
+    }
+
+    /// The bookkeeping of the playback on the current tick
+    pub fn snapshot(&self) -> PlaybackSnapshot {
+        PlaybackSnapshot {
+            state: self.state,
+            current_tick: self.current_tick,
+            next_line: self.next_line,
+            waits_done: self.waits_done,
+            wait_start: self.wait_start,
+            tick_offset: self.tick_offset,
+            last_puzzle_unlock: self.last_puzzle_unlock,
+            last_line_tick: self.last_line_tick,
+            line_ticks: self.line_ticks.clone(),
+            releases: self.releases.clone(),
+            script: self.script.clone(),
+            controller: self.restored.unwrap_or(self.controller.current),
+        }
+    }
+
+    /// Goes back to the tick of `snapshot`, with the script it was playing.
+    /// The playback is paused there, unless it was stopped.
+    pub fn restore(&mut self, snapshot: PlaybackSnapshot) {
+        self.state = match snapshot.state {
+            PlaybackState::Stopped => PlaybackState::Stopped,
+            _ => PlaybackState::Paused,
+        };
+        self.current_tick = snapshot.current_tick;
+        self.next_line = snapshot.next_line;
+        self.waits_done = snapshot.waits_done;
+        self.wait_start = snapshot.wait_start;
+        self.tick_offset = snapshot.tick_offset;
+        self.last_puzzle_unlock = snapshot.last_puzzle_unlock;
+        self.last_line_tick = snapshot.last_line_tick;
+        self.line_ticks = snapshot.line_ticks;
+        self.releases = snapshot.releases;
+        self.script = snapshot.script;
+        // A stopped playback releases what the game holds by itself
+        self.restored = (self.state != PlaybackState::Stopped).then_some(snapshot.controller);
+    }
+
+    pub fn skip_to(&mut self, tick: u32) {
+        self.skipto_tick = tick;
+        self.skipto_label = None;
+    }
+
+    /// Skip until the line following the label has played
+    pub fn skip_to_label(&mut self, label: String) {
+        self.skipto_label = Some(label);
+    }
+
+    pub fn pause_at(&mut self, tick: u32) {
+        self.pauseat_tick = tick;
+        self.pauseat_label = None;
+    }
+
+    /// Pause after the line following the label has played
+    pub fn pause_at_label(&mut self, label: String) {
+        self.pauseat_tick = 0;
+        self.pauseat_label = Some(label);
+    }
+
+    /// Get the controller input for `tick`, and possibly advance state.
+    /// Returns `None` when there is nothing to send to the game.
+    pub fn tick(&mut self, tick: u32, game: &mut impl GameInterface) -> Option<&ControllerState> {
+        // If we are not running, exit
+        if self.state == PlaybackState::Stopped {
+            // Release all buttons if not done already
+            if self.controller.current != HalfControllerState::default() {
+                self.controller.previous = self.controller.current;
+                self.controller.current = HalfControllerState::default();
+                return Some(&self.controller);
+            }
+
+            return None;
+        }
+
+        let legit = self.is_legit();
+        let script = self.script.as_ref()?;
+
+        // Keep playing until the last held key is released
+        if self.next_line >= script.lines.len() && self.releases.is_empty() {
+            self.stop();
+            return None;
+        }
+
+        if tick == 0 {
+            game.reset_rng();
+        }
+        if self.current_tick != tick {
+            self.send
+                .send(TasToControllerMessage::CurrentTick(tick))
+                .unwrap();
+
+            if tick == self.pauseat_tick {
+                self.state = PlaybackState::Paused;
+
+                self.send
+                    .send(TasToControllerMessage::PlaybackState(
+                        self.get_playback_state(),
+                    ))
+                    .unwrap();
+            }
+
+            self.current_tick = tick;
+
+            self.controller.previous = self.controller.current;
+            if let Some(restored) = self.restored.take() {
+                self.controller.current = restored;
+            }
+
+            // Release the held keys and the clicks whose time is up
+            for (_, input) in self.releases.iter().filter(|(release, _)| *release <= tick) {
+                self.controller.current.release(*input);
+            }
+            self.releases.retain(|(release, _)| *release > tick);
+
+            // Only held keys are left to release after the last line
+            let Some(next_line) = script.lines.get(self.next_line) else {
+                return Some(&self.controller);
+            };
+
+            // Wait for the events before the line. Its tick is then counted
+            // from the last one, and the following lines are delayed as well
+            while let Some(wait) = next_line.waits.get(self.waits_done) {
+                let Some(event_tick) = self.wait_resolved(wait, tick, game) else {
+                    break;
+                };
+
+                info!("Done waiting for {wait:?} on tick {event_tick}");
+                self.waits_done += 1;
+                self.wait_start = event_tick + 1;
+                self.tick_offset = event_tick - (next_line.absolute_tick - next_line.tick);
+            }
+
+            if self.waits_done == next_line.waits.len()
+                && next_line.absolute_tick + self.tick_offset == tick
+            {
+                let line_index = self.next_line;
+                self.next_line += 1;
+                self.waits_done = 0;
+                self.wait_start = tick;
+
+                for press in &next_line.keys {
+                    let input = press.key.action.input();
+                    let hold = match press.key.action {
+                        KeyAction::Release(_) => {
+                            self.controller.current.release(input);
+                            continue;
+                        }
+                        KeyAction::Press(_) => press.hold,
+                        // Taps and clicks last a single tick unless held
+                        KeyAction::Tap(_) | KeyAction::Click(_) => Some(press.hold.unwrap_or(1)),
+                    };
+
+                    self.controller.current.press(input);
+                    // The script checked that the holds end before the largest tick
+                    if let Some(hold) = hold {
+                        self.releases.push((tick + hold, input));
+                    }
+                }
+
+                if let Some(mouse) = next_line.mouse {
+                    self.controller.current.mouse_pos = mouse;
+                }
+
+                // Execute the tools
+                for tool in next_line.tools.iter().flatten() {
+                    if legit && !tool.is_legit() {
+                        error!("Not using `{}`, it is not legitimate", tool.name());
+                        continue;
+                    }
+
+                    match tool {
+                        Tool::SetPos { pos, ang } => {
+                            game.set_player_pos(*pos);
+                            game.set_player_ang(*ang);
+                        }
+                        Tool::SetAng { ang } => game.set_player_ang(*ang),
+                        Tool::LookAt { target } => {
+                            let direction = *target - game.player_pos();
+                            game.set_player_ang(direction.to_angles());
+                        }
+                        Tool::Assert(assertion) => {
+                            let Some(failure) = self.check_assertion(assertion, tick, game) else {
+                                continue;
+                            };
+
+                            error!(
+                                "Assertion failed on tick {}: expected {}, got {}",
+                                failure.tick, failure.expected, failure.actual
+                            );
+                            self.send
+                                .send(TasToControllerMessage::AssertionFailed(failure))
+                                .unwrap();
+
+                            if self.pause_on_assertion_failure {
+                                self.state = PlaybackState::Paused;
+                                self.send
+                                    .send(TasToControllerMessage::PlaybackState(self.state))
+                                    .unwrap();
+                            }
+                        }
+                    }
+                }
+
+                self.last_line_tick = tick;
+                self.line_ticks.push(tick);
+
+                let pause_label = script.labels.iter().any(|label| {
+                    label.line == line_index && self.pauseat_label.as_ref() == Some(&label.name)
+                });
+                if pause_label {
+                    self.state = PlaybackState::Paused;
+                    self.send
+                        .send(TasToControllerMessage::PlaybackState(self.state))
+                        .unwrap();
+                }
+            }
+        }
+
+        // Return it
+        Some(&self.controller)
+    }
+
+    /// Records that a puzzle unlocked on `tick`
+    pub fn puzzle_unlocked(&mut self, tick: u32) {
+        self.last_puzzle_unlock = Some(tick);
+        self.send
+            .send(TasToControllerMessage::PuzzleUnlock(tick))
+            .unwrap();
+    }
+
+    /// Checks the assertion against the game, and returns what went wrong if it failed
+    fn check_assertion(
+        &self,
+        assertion: &Assertion,
+        tick: u32,
+        game: &impl GameInterface,
+    ) -> Option<AssertionFailure> {
+        match assertion {
+            Assertion::Position { pos, within } => {
+                let actual = game.player_pos();
+                // len is the squared length
+                let distance = (actual - *pos).len().sqrt();
+                (distance > *within).then(|| AssertionFailure {
+                    tick,
+                    expected: format!("position {} {} {} within {within}", pos.x, pos.y, pos.z),
+                    actual: format!(
+                        "position {} {} {}, {distance} away",
+                        actual.x, actual.y, actual.z
+                    ),
+                })
+            }
+            Assertion::Unlocked => {
+                let since = self.last_line_tick;
+                let actual = match self.last_puzzle_unlock {
+                    Some(unlock) if unlock >= since => return None,
+                    Some(unlock) => format!("last unlock on tick {unlock}"),
+                    None => "no puzzle unlocked".to_string(),
+                };
+                Some(AssertionFailure {
+                    tick,
+                    expected: format!("a puzzle unlocked since tick {since}"),
+                    actual,
+                })
+            }
+        }
+    }
+
+    /// Returns the tick on which the event happened, if it did
+    fn wait_resolved(
+        &self,
+        wait: &Wait,
+        current_tick: u32,
+        game: &impl GameInterface,
+    ) -> Option<u32> {
+        match wait {
+            Wait::PuzzleUnlock => self
+                .last_puzzle_unlock
+                .filter(|&tick| tick >= self.wait_start),
+            Wait::Interaction(status) => {
+                (game.interaction_status() == Some(*status)).then_some(current_tick)
+            }
+        }
+    }
+
+    pub fn should_do_skipping(&self) -> bool {
+        // Only skip after 60 frames, the "eyes opening" animation fucks things up
+        self.state == PlaybackState::Playing && self.before_skip_target() && self.current_tick > 60
+    }
+
+    /// Whether the tick or the line to skip to is not reached yet
+    fn before_skip_target(&self) -> bool {
+        let Some(name) = &self.skipto_label else {
+            return self.current_tick < self.skipto_tick;
+        };
+
+        // Skip until the line following the label has played
+        self.script
+            .as_ref()
+            .and_then(|script| script.labels.iter().find(|label| &label.name == name))
+            .is_some_and(|label| self.next_line <= label.line)
+    }
+
+    pub fn get_playback_state(&self) -> PlaybackState {
+        if self.state == PlaybackState::Playing && self.before_skip_target() {
+            PlaybackState::Skipping
+        } else {
+            self.state
+        }
+    }
+
+    /// Whether the tools and hotkeys that are not legitimate are disabled:
+    /// in legit mode, or while a script with the `legit` directive plays.
+    pub fn is_legit(&self) -> bool {
+        let legit_script = self
+            .script
+            .as_ref()
+            .is_some_and(|script| script.metadata.legit);
+
+        self.legit_mode || (legit_script && self.state != PlaybackState::Stopped)
+    }
+
+    pub fn get_current_tick(&self) -> u32 {
+        self.current_tick
+    }
+
+    /// The script last started, even once stopped
+    pub fn script(&self) -> Option<&Script> {
+        self.script.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::{GameInterface, HalfControllerState, Playback};
+    use crate::communication::TasToControllerMessage;
+    use crate::recording::Recorder;
+    use crate::script::{Input, Script};
+    use crate::tas::PlaybackState;
+    use crate::witness::windows_types::VirtualKeyCode;
+    use crate::witness::witness_types::{InteractionStatus, Vec2, Vec3};
+
+    struct FakeGame {
+        pos: Vec3,
+        ang: Vec2,
+        status: Option<InteractionStatus>,
+        rng_resets: u32,
+    }
+
+    impl GameInterface for FakeGame {
+        fn player_pos(&self) -> Vec3 {
+            self.pos
+        }
+
+        fn set_player_pos(&mut self, pos: Vec3) {
+            self.pos = pos;
+        }
+
+        fn player_ang(&self) -> Vec2 {
+            self.ang
+        }
+
+        fn set_player_ang(&mut self, ang: Vec2) {
+            self.ang = ang;
+        }
+
+        fn interaction_status(&self) -> Option<InteractionStatus> {
+            self.status
+        }
+
+        fn reset_rng(&mut self) {
+            self.rng_resets += 1;
+        }
+    }
+
+    impl FakeGame {
+        fn new() -> Self {
+            Self {
+                pos: Vec3 {
+                    x: 0.,
+                    y: 0.,
+                    z: 0.,
+                },
+                ang: Vec2 { x: 0., y: 0. },
+                status: None,
+                rng_resets: 0,
+            }
+        }
+    }
+
+    /// A playback of `body`, started on tick 0 of the game
+    fn start(body: &str) -> (Playback, FakeGame, Receiver<TasToControllerMessage>) {
+        let script = Script::try_from(format!("version 1\nstart now\n{body}")).unwrap();
+        let (send, recv) = channel();
+        let mut playback = Playback::new(send);
+        playback.start(script);
+
+        (playback, FakeGame::new(), recv)
+    }
+
+    /// Plays the ticks up to `last`, and returns the controller state of each tick
+    fn play(playback: &mut Playback, game: &mut FakeGame, last: u32) -> Vec<HalfControllerState> {
+        (playback.get_current_tick()..=last)
+            .map(|tick| {
+                playback
+                    .tick(tick, game)
+                    .map(|controller| controller.current)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inputs() {
+        let (mut playback, mut game, _recv) = start("1>U|10 0\n+2>u|0 0\n");
+
+        let states = play(&mut playback, &mut game, 3);
+        let forward: Vec<_> = states
+            .iter()
+            .map(|state| {
+                state
+                    .keys
+                    .difference(&Default::default())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let w = VirtualKeyCode::W as u32;
+        assert_eq!(forward, vec![vec![], vec![w], vec![w], vec![]]);
+        assert_eq!(states[1].mouse_pos, (10, 0));
+        assert_eq!(states[3].mouse_pos, (0, 0));
+        assert_eq!(game.rng_resets, 1);
+
+        // The script stops after its last line, and releases everything
+        assert_eq!(playback.get_playback_state(), PlaybackState::Playing);
+        assert!(playback.tick(4, &mut game).is_none());
+        assert_eq!(playback.get_playback_state(), PlaybackState::Stopped);
+    }
+
+    #[test]
+    fn test_click_release() {
+        let (mut playback, mut game, _recv) = start("1>P\n+1>p:2\n+5>\n");
+
+        let clicks: Vec<_> = play(&mut playback, &mut game, 5)
+            .iter()
+            .map(|state| (state.left_click, state.right_click))
+            .collect();
+        assert_eq!(
+            clicks,
+            vec![
+                (false, false),
+                (true, false),
+                (false, true),
+                (false, true),
+                (false, false),
+                (false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_final_hold() {
+        let (mut playback, mut game, _recv) = start("1>U:3\n");
+        assert_eq!(playback.script().unwrap().lines.len(), 1);
+
+        let held = |state: &HalfControllerState| state.keys != Default::default();
+        playback.pause_at(2);
+        let states = play(&mut playback, &mut game, 2);
+        assert_eq!(
+            states.iter().map(held).collect::<Vec<_>>(),
+            [false, true, true]
+        );
+        // The key is still held by the script
+        assert!(playback.take_over().is_err());
+
+        // The script plays until the key is released
+        playback.resume();
+        let states = play(&mut playback, &mut game, 4);
+        assert_eq!(
+            states.iter().map(held).collect::<Vec<_>>(),
+            [true, true, false]
+        );
+        assert_eq!(playback.get_playback_state(), PlaybackState::Playing);
+        assert!(playback.tick(5, &mut game).is_none());
+        assert_eq!(playback.get_playback_state(), PlaybackState::Stopped);
+    }
+
+    #[test]
+    fn test_pause() {
+        let (mut playback, mut game, recv) = start("1>U\n@label stop\n+5>u\n+5>\n");
+        playback.pause_at(3);
+
+        play(&mut playback, &mut game, 3);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Paused);
+        assert!(recv.try_iter().any(|msg| matches!(
+            msg,
+            TasToControllerMessage::PlaybackState(PlaybackState::Paused)
+        )));
+
+        // Each tick played while paused is a single frame
+        play(&mut playback, &mut game, 4);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Paused);
+        assert_eq!(playback.get_current_tick(), 4);
+
+        // Pause after the line following the label
+        playback.pause_at_label("stop".to_string());
+        playback.resume();
+        play(&mut playback, &mut game, 5);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Playing);
+        play(&mut playback, &mut game, 6);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Paused);
+    }
+
+    #[test]
+    fn test_skip() {
+        let (mut playback, mut game, _recv) = start("1>U\n+100>u\n@label end\n+50>U\n+1>u\n");
+        playback.skip_to(80);
+
+        play(&mut playback, &mut game, 60);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Skipping);
+        // The first ticks are never skipped
+        assert!(!playback.should_do_skipping());
+        play(&mut playback, &mut game, 79);
+        assert!(playback.should_do_skipping());
+        play(&mut playback, &mut game, 80);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Playing);
+        assert!(!playback.should_do_skipping());
+
+        playback.skip_to_label("end".to_string());
+        play(&mut playback, &mut game, 150);
+        assert!(playback.should_do_skipping());
+        play(&mut playback, &mut game, 151);
+        assert!(!playback.should_do_skipping());
+    }
+
+    #[test]
+    fn test_tools() {
+        let (mut playback, mut game, recv) = start(
+            "1>||setpos 1 2 3 0.5 0
++1>||lookat 1 5 3
++1>||assert pos 1 2 3 within 0.1
++1>||assert pos 10 2 3 within 0.1
+",
+        );
+        playback.pause_on_assertion_failure = true;
+
+        play(&mut playback, &mut game, 2);
+        assert_eq!(
+            game.pos,
+            Vec3 {
+                x: 1.,
+                y: 2.,
+                z: 3.
+            }
+        );
+        assert_eq!(
+            game.ang,
+            Vec3 {
+                x: 0.,
+                y: 3.,
+                z: 0.
+            }
+            .to_angles()
+        );
+
+        play(&mut playback, &mut game, 3);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Playing);
+        play(&mut playback, &mut game, 4);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Paused);
+        let failures: Vec<_> = recv
+            .try_iter()
+            .filter_map(|msg| match msg {
+                TasToControllerMessage::AssertionFailed(failure) => Some(failure),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].tick, 4);
+
+        // Legit mode does not apply the tools that are not legitimate
+        let (mut playback, mut game, _recv) = start("1>||setpos 1 2 3 0 0\n");
+        playback.legit_mode = true;
+        play(&mut playback, &mut game, 1);
+        assert_eq!(
+            game.pos,
+            Vec3 {
+                x: 0.,
+                y: 0.,
+                z: 0.
+            }
+        );
+    }
+
+    #[test]
+    fn test_take_over() {
+        let body = "1>U|5 0 // walk\n+10>u|0 0\n+10>D\n";
+        let (mut playback, mut game, _recv) = start(body);
+        assert!(playback.take_over().is_err());
+
+        // The inputs of the script are released when the player takes over
+        playback.pause_at(5);
+        play(&mut playback, &mut game, 5);
+        let takeover = playback.take_over().unwrap();
+        assert_eq!(playback.get_playback_state(), PlaybackState::Stopped);
+        let mut recorder = Recorder::after(&takeover.controller);
+        recorder.press(2, Input::Keyboard(VirtualKeyCode::A));
+        let text = takeover.text(recorder.finish(4));
+        assert_eq!(text, "6>u|0 0\n+1>L\n+2>l\n");
+
+        let src = format!("version 1\nstart now\n{body}");
+        assert_eq!(
+            takeover.append(&src, &text),
+            "version 1\nstart now\n1>U|5 0 // walk\n6>u|0 0\n+1>L\n+2>l\n"
+        );
+
+        // The lines still playing, and the ones in blocks cannot be cut
+        let (mut playback, mut game, _recv) = start("1..10>|linear(100, 0)\n+5>U\n");
+        playback.pause_at(5);
+        play(&mut playback, &mut game, 5);
+        assert!(playback.take_over().is_err());
+
+        let (mut playback, mut game, _recv) = start("repeat 2 {\n    +1>U\n    +1>u\n}\n");
+        playback.pause_at(3);
+        play(&mut playback, &mut game, 3);
+        assert!(playback.take_over().is_err());
+    }
+
+    #[test]
+    fn test_play_recording() {
+        // The first tick of a recording plays on the first tick of the playback
+        let w = Input::Keyboard(VirtualKeyCode::W);
+        let mut recorder = Recorder::new();
+        recorder.mouse(0, (5, -1));
+        recorder.press(0, w);
+        recorder.release(2, w);
+        let lines = recorder.finish(3);
+        let text = Script::from_lines(&lines).print_body(&Default::default());
+        let (mut playback, mut game, _recv) = start(&text);
+
+        let states = play(&mut playback, &mut game, 4);
+        let played: Vec<_> = states
+            .iter()
+            .map(|state| (state.keys != Default::default(), state.mouse_pos))
+            .collect();
+        assert_eq!(
+            played,
+            vec![
+                (false, (0, 0)),
+                (true, (5, -1)),
+                (true, (0, 0)),
+                (false, (0, 0)),
+                (false, (0, 0)),
+            ]
+        );
+        assert!(playback.tick(5, &mut game).is_none());
+        assert_eq!(playback.get_playback_state(), PlaybackState::Stopped);
+    }
+
+    #[test]
+    fn test_take_over_keeps_macros() {
+        let body = "tap()\n+5>U\n@label back\n+5>u\nmacro tap() {\n    +1>P\n}\n+5>D\n";
+        let (mut playback, mut game, _recv) = start(body);
+        playback.pause_at(8);
+        play(&mut playback, &mut game, 8);
+        let takeover = playback.take_over().unwrap();
+        let text = takeover.text(Recorder::after(&takeover.controller).finish(2));
+
+        // The macro used before the cut is still defined after it
+        let src = format!("version 1\nstart now\n{body}");
+        let appended = takeover.append(&src, &text);
+        assert_eq!(
+            appended,
+            "version 1\nstart now\ntap()\n+5>U\n9>u\n\n// @label back\n\nmacro tap() {\n    +1>P\n}\n"
+        );
+        let script = Script::try_from(appended).unwrap();
+        let ticks: Vec<_> = script.lines.iter().map(|line| line.absolute_tick).collect();
+        assert_eq!(ticks, vec![1, 6, 9]);
+    }
+
+    #[test]
+    fn test_reload() {
+        let body = "1>U\n+5>u\n+5>D\n+5>d\n";
+        let reload =
+            |body: &str| Script::try_from(format!("version 1\nstart now\n{body}")).unwrap();
+        let (mut playback, mut game, _recv) = start(body);
+        play(&mut playback, &mut game, 7);
+
+        // The lines after the current tick can change
+        assert_eq!(
+            playback.reload(reload("1>U\n+5>u // walk\n+8>D\n+1>d\n")),
+            Ok(())
+        );
+        let held = |state: &HalfControllerState| state.keys != Default::default();
+        let states = play(&mut playback, &mut game, 15);
+        assert_eq!(
+            states.iter().map(held).collect::<Vec<_>>(),
+            vec![false, false, false, false, false, false, false, true, false]
+        );
+
+        let (mut playback, mut game, _recv) = start(body);
+        play(&mut playback, &mut game, 7);
+        // A played line changed
+        assert_eq!(playback.reload(reload("1>U\n+3>u\n+5>D\n")), Err(4));
+        assert_eq!(playback.reload(reload("1>U\n+7>u\n+5>D\n")), Err(6));
+        // A line was added before the current tick
+        assert_eq!(playback.reload(reload("1>U\n+5>u\n+1>D\n")), Err(7));
+        assert_eq!(
+            playback.reload(reload("1>U\n+5>u\n\nwait puzzle_unlock\n+5>D\n")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_waits() {
+        let (mut playback, mut game, _recv) =
+            start("1>U\nwait puzzle_unlock\n+2>u\nwait interaction == Walking\n+1>D\n");
+
+        play(&mut playback, &mut game, 10);
+        playback.puzzle_unlocked(10);
+        let states = play(&mut playback, &mut game, 13);
+        let held = |state: &HalfControllerState| state.keys != Default::default();
+        // Released 2 ticks after the unlock
+        assert_eq!(
+            states.iter().map(held).collect::<Vec<_>>(),
+            vec![true, true, false, false]
+        );
+
+        play(&mut playback, &mut game, 20);
+        game.status = Some(InteractionStatus::Walking);
+        let states = play(&mut playback, &mut game, 22);
+        assert_eq!(
+            states.iter().map(held).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let w = VirtualKeyCode::W as u32;
+        let s = VirtualKeyCode::S as u32;
+        let keys = |state: &HalfControllerState| {
+            state
+                .keys
+                .difference(&Default::default())
+                .collect::<Vec<_>>()
+        };
+        let (mut playback, mut game, _recv) = start("1>U\n+5>u\n+5>D\n+5>d\n");
+        play(&mut playback, &mut game, 3);
+        let snapshot = playback.snapshot();
+        assert_eq!(snapshot.tick(), 3);
+
+        let states = play(&mut playback, &mut game, 12);
+        assert_eq!(keys(states.last().unwrap()), vec![s]);
+
+        playback.restore(snapshot);
+        assert_eq!(playback.get_playback_state(), PlaybackState::Paused);
+        assert_eq!(playback.get_current_tick(), 3);
+
+        // The game goes from the inputs it holds to the restored ones
+        let controller = playback.tick(4, &mut game).unwrap();
+        assert_eq!(keys(&controller.previous), vec![s]);
+        assert_eq!(keys(&controller.current), vec![w]);
+
+        // The lines after the snapshot play again
+        let states = play(&mut playback, &mut game, 12);
+        let held: Vec<_> = states.iter().map(keys).collect();
+        assert_eq!(held[..3], [vec![w], vec![w], vec![]]);
+        assert_eq!(held.last().unwrap(), &vec![s]);
+    }
+}
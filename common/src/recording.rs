@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use crate::playback::HalfControllerState;
+use crate::script::{Input, KeyAction, KeyPress, ScriptLine, KEYS};
+use crate::witness::windows_types::VirtualKeyCode;
+
+/// Where the key that started an input was written
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    /// The tick the input was pressed on
+    tick: u32,
+    /// Index of the line and of the key in the line
+    line: usize,
+    key: usize,
+}
+
+/// Turns the inputs of the player into script lines.
+/// The ticks are counted from the start of the recording.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    /// Added to the ticks given to the recorder, as the playback starts on tick 1
+    offset: u32,
+    /// The tick whose inputs are being collected
+    tick: u32,
+    /// Inputs pressed and released during the tick, in order
+    pressed: Vec<Input>,
+    released: Vec<Input>,
+    /// The mouse movement of the tick. The deltas read during a tick add up.
+    mouse: (i32, i32),
+    /// Keys to write on the tick, along with the recorded ones
+    keys: Vec<KeyPress>,
+
+    /// The mouse movement of the last line that set one
+    last_mouse: (i32, i32),
+    /// The tick of the last line
+    last_line_tick: u32,
+    held: HashMap<Input, Held>,
+    lines: Vec<ScriptLine>,
+    /// Inputs that are not meant for the game, like the hotkeys of the tool
+    ignored: Vec<Input>,
+}
+
+impl Recorder {
+    /// Records the inputs from the start of a script, the first tick of the
+    /// recording being tick 1
+    pub fn new() -> Self {
+        Self {
+            offset: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Records the inputs that follow the ones played by a script. The keys it
+    /// holds are released and its mouse movement stopped on the first tick.
+    pub fn after(controller: &HalfControllerState) -> Self {
+        let releases = controller
+            .keys
+            .difference(&Default::default())
+            .filter_map(|code| {
+                let code = VirtualKeyCode::try_from(code).ok()?;
+                KEYS.iter()
+                    .find(|key| key.action == KeyAction::Release(code))
+                    .map(|&key| KeyPress { key, hold: None })
+            });
+
+        Self {
+            tick: 1,
+            keys: releases.collect(),
+            last_mouse: controller.mouse_pos,
+            ..Default::default()
+        }
+    }
+
+    /// Leaves `inputs` out of the recording
+    pub fn ignoring(mut self, inputs: impl IntoIterator<Item = Input>) -> Self {
+        self.ignored.extend(inputs);
+        self
+    }
+
+    /// Records an input going down on `tick`
+    pub fn press(&mut self, tick: u32, input: Input) {
+        if !self.ignored.contains(&input) {
+            self.advance(tick + self.offset);
+            self.pressed.push(input);
+        }
+    }
+
+    /// Records an input going up on `tick`
+    pub fn release(&mut self, tick: u32, input: Input) {
+        if !self.ignored.contains(&input) {
+            self.advance(tick + self.offset);
+            self.released.push(input);
+        }
+    }
+
+    /// Records a mouse movement read on `tick`
+    pub fn mouse(&mut self, tick: u32, delta: (i32, i32)) {
+        self.advance(tick + self.offset);
+        self.mouse.0 += delta.0;
+        self.mouse.1 += delta.1;
+    }
+
+    /// Ends the recording on `tick`, releasing the inputs still held and stopping the mouse.
+    /// Returns the recorded lines, with relative ticks.
+    pub fn finish(mut self, tick: u32) -> Vec<ScriptLine> {
+        self.advance(tick + self.offset);
+        self.released.extend(self.held.keys().copied());
+        self.mouse = (0, 0);
+        self.write_tick();
+
+        self.lines
+    }
+
+    /// Writes the inputs of the current tick if `tick` is a later one
+    fn advance(&mut self, tick: u32) {
+        if tick <= self.tick {
+            return;
+        }
+
+        self.write_tick();
+        // The mouse did not move during the ticks with nothing recorded
+        if tick > self.tick + 1 && self.last_mouse != (0, 0) {
+            self.tick += 1;
+            self.write_tick();
+        }
+        self.tick = tick;
+    }
+
+    /// Writes a line with the inputs that changed during the current tick, if any did
+    fn write_tick(&mut self) {
+        let tick = self.tick;
+        let index = self.lines.len();
+        let mut keys = std::mem::take(&mut self.keys);
+
+        for input in std::mem::take(&mut self.pressed) {
+            // Keys repeat while held, and only some of them can be written in scripts
+            let key = KEYS.iter().find(|key| {
+                key.action.input() == input && !matches!(key.action, KeyAction::Release(_))
+            });
+            let Some(&key) = key.filter(|_| !self.held.contains_key(&input)) else {
+                continue;
+            };
+
+            let held = Held {
+                tick,
+                line: index,
+                key: keys.len(),
+            };
+            self.held.insert(input, held);
+            keys.push(KeyPress { key, hold: None });
+        }
+
+        for input in std::mem::take(&mut self.released) {
+            let Some(held) = self.held.remove(&input) else {
+                continue;
+            };
+
+            let press = match held.line {
+                line if line == index => &mut keys[held.key],
+                line => &mut self.lines[line].keys[held.key],
+            };
+            let hold = (tick - held.tick).max(1);
+            match press.key.action {
+                // Keys pressed and released on the same tick are held for that tick
+                KeyAction::Press(_) if held.tick == tick => press.hold = Some(1),
+                KeyAction::Press(code) => {
+                    let release = KEYS
+                        .iter()
+                        .find(|key| key.action == KeyAction::Release(code))
+                        .copied();
+                    if let Some(key) = release {
+                        keys.push(KeyPress { key, hold: None });
+                    }
+                }
+                // Taps and clicks last one tick unless held
+                _ => press.hold = (hold > 1).then_some(hold),
+            }
+        }
+
+        let mouse = std::mem::take(&mut self.mouse);
+        let mouse = (mouse != self.last_mouse).then_some(mouse);
+        if keys.is_empty() && mouse.is_none() {
+            return;
+        }
+
+        if let Some(mouse) = mouse {
+            self.last_mouse = mouse;
+        }
+        self.lines.push(ScriptLine {
+            relative: true,
+            tick: tick - self.last_line_tick,
+            absolute_tick: tick,
+            keys,
+            mouse,
+            tools: None,
+            span: 0..0,
+            file: None,
+            generated: false,
+            comments: vec![],
+            inline_comment: None,
+            waits: vec![],
+        });
+        self.last_line_tick = tick;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recorder;
+    use crate::script::{Input, MouseButton, PrintOptions, Script};
+    use crate::witness::windows_types::VirtualKeyCode;
+
+    fn print(recorder: Recorder, tick: u32) -> String {
+        let lines = recorder.finish(tick);
+        Script::from_lines(&lines).print_body(&PrintOptions::default())
+    }
+
+    #[test]
+    fn test_keys() {
+        let w = Input::Keyboard(VirtualKeyCode::W);
+        let mut recorder = Recorder::new();
+        recorder.press(2, w);
+        // Held keys repeat
+        recorder.press(3, w);
+        recorder.press(3, Input::Keyboard(VirtualKeyCode::LShift));
+        // Keys that cannot be written are left out
+        recorder.press(4, Input::Keyboard(VirtualKeyCode::Q));
+        recorder.release(10, w);
+        recorder.press(12, Input::Keyboard(VirtualKeyCode::A));
+        recorder.release(12, Input::Keyboard(VirtualKeyCode::A));
+
+        assert_eq!(print(recorder, 20), "+3>U\n+1>S\n+7>u\n+2>L:1\n+8>s\n");
+    }
+
+    #[test]
+    fn test_taps_and_clicks() {
+        let left = Input::Mouse(MouseButton::Left);
+        let mut recorder = Recorder::new();
+        recorder.press(1, left);
+        recorder.release(1, left);
+        recorder.press(5, Input::Mouse(MouseButton::Right));
+        recorder.release(9, Input::Mouse(MouseButton::Right));
+        recorder.press(10, Input::Keyboard(VirtualKeyCode::Space));
+        recorder.press(11, left);
+
+        // The inputs still held are released at the end
+        assert_eq!(print(recorder, 13), "+2>P\n+4>p:4\n+5>[SPACE]:3\n+1>P:2\n");
+    }
+
+    #[test]
+    fn test_ignored() {
+        let esc = Input::Keyboard(VirtualKeyCode::ESC);
+        let mut recorder = Recorder::new().ignoring([esc]);
+        recorder.press(2, esc);
+        recorder.release(2, esc);
+        recorder.press(3, Input::Keyboard(VirtualKeyCode::W));
+        recorder.press(5, esc);
+
+        assert_eq!(print(recorder, 6), "+4>U\n+3>u\n");
+    }
+
+    #[test]
+    fn test_mouse() {
+        let mut recorder = Recorder::new();
+        for tick in 0..3 {
+            recorder.mouse(tick, (5, -1));
+        }
+        recorder.mouse(3, (2, 0));
+        recorder.mouse(3, (1, 0));
+        recorder.mouse(4, (3, 0));
+        // Nothing was read on ticks 5 and 6
+        recorder.mouse(7, (3, 0));
+
+        let text = print(recorder, 8);
+        assert_eq!(text, "+1>|5 -1\n+3>|3 0\n+2>|0 0\n+2>|3 0\n+1>|0 0\n");
+
+        // What is recorded plays the same inputs, one tick later as the playback starts on tick 1
+        let script = Script::try_from(format!("version 1\nstart now\n{text}")).unwrap();
+        let played: Vec<_> = script
+            .lines
+            .iter()
+            .map(|line| (line.absolute_tick, line.mouse))
+            .collect();
+        assert_eq!(
+            played,
+            vec![
+                (1, Some((5, -1))),
+                (4, Some((3, 0))),
+                (6, Some((0, 0))),
+                (8, Some((3, 0))),
+                (9, Some((0, 0))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+/// How an interpolated mouse movement is spread over its ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// The same movement on every tick
+    Linear,
+    /// Starts and ends slowly, fastest in the middle
+    Ease,
+}
+
+impl Curve {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Curve::Linear => "linear",
+            Curve::Ease => "ease",
+        }
+    }
+
+    /// Returns the part of the movement done at `t`, between 0 and 1
+    fn progress(&self, t: f64) -> f64 {
+        match self {
+            Curve::Linear => t,
+            Curve::Ease => t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    /// Splits `total` in `ticks` integer movements following the curve.
+    /// Each tick moves to the rounded position of the curve, so the rounding
+    /// errors are carried forward and the movements add up to exactly `total`.
+    pub(crate) fn spread(&self, total: i32, ticks: u32) -> Vec<i32> {
+        let mut done = 0;
+
+        (1..=ticks)
+            .map(|tick| {
+                let position =
+                    (total as f64 * self.progress(tick as f64 / ticks as f64)).round() as i32;
+                let movement = position - done;
+                done = position;
+                movement
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Curve;
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(Curve::Linear.spread(10, 4), vec![3, 2, 3, 2]);
+        assert_eq!(Curve::Linear.spread(-40, 3), vec![-13, -14, -13]);
+        assert_eq!(Curve::Linear.spread(5, 1), vec![5]);
+        assert_eq!(Curve::Ease.spread(300, 6), vec![22, 56, 72, 72, 56, 22]);
+
+        for curve in [Curve::Linear, Curve::Ease] {
+            for (total, ticks) in [(300, 20), (-40, 20), (7, 13), (0, 5), (-1, 3)] {
+                let movements = curve.spread(total, ticks);
+                assert_eq!(movements.len(), ticks as usize);
+                assert_eq!(movements.iter().sum::<i32>(), total);
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+
+use chumsky::error::{Simple, SimpleReason};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error found while parsing or checking a script, with its location.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptError {
+    /// The file the error comes from, if the script was loaded from a file
+    pub file: Option<String>,
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// Column of the error in characters, starting at 1
+    pub column: usize,
+    /// Byte range of the error in the file
+    pub span: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+    /// The tokens that would have been valid at this location
+    pub expected: Vec<String>,
+    /// The token that was found instead
+    pub found: Option<String>,
+    pub hint: Option<String>,
+    /// How the location of the error was reached: repeat blocks, macro calls and includes.
+    /// The innermost one comes first.
+    pub context: Vec<String>,
+}
+
+/// Returns the line and column of the character at `offset` in `src`, starting at 1
+pub(crate) fn position(src: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+    for c in src.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    (line, column)
+}
+
+/// Converts a range of characters to a range of bytes in `src`
+fn byte_span(src: &str, span: &Range<usize>) -> Range<usize> {
+    let byte_offset = |offset| {
+        src.char_indices()
+            .nth(offset)
+            .map_or(src.len(), |(index, _)| index)
+    };
+
+    byte_offset(span.start)..byte_offset(span.end)
+}
+
+impl ScriptError {
+    /// Creates an error located at `span`, a range of characters of `src`
+    pub(crate) fn new(
+        message: impl Into<String>,
+        span: Range<usize>,
+        src: &str,
+        file: Option<&Path>,
+    ) -> Self {
+        let (line, column) = position(src, span.start);
+
+        Self {
+            file: file.map(|path| path.display().to_string()),
+            line,
+            column,
+            span: byte_span(src, &span),
+            severity: Severity::Error,
+            message: message.into(),
+            expected: vec![],
+            found: None,
+            hint: None,
+            context: vec![],
+        }
+    }
+
+    /// Converts an error from the parser
+    pub(crate) fn from_parse_error(err: &Simple<char>, src: &str, file: Option<&Path>) -> Self {
+        let message = match err.reason() {
+            SimpleReason::Unexpected => match err.found() {
+                Some(found) => format!("Unexpected {found:?}"),
+                None => "Unexpected end of input".to_string(),
+            },
+            SimpleReason::Unclosed { delimiter, .. } => format!("Unclosed {delimiter:?}"),
+            SimpleReason::Custom(msg) => msg.clone(),
+        };
+
+        let mut expected: Vec<String> = err
+            .expected()
+            .map(|token| match token {
+                Some(c) => format!("{c:?}"),
+                None => "end of input".to_string(),
+            })
+            .collect();
+        expected.sort();
+
+        Self {
+            expected,
+            found: err.found().map(|c| format!("{c:?}")),
+            ..Self::new(message, err.span(), src, file)
+        }
+    }
+
+    pub(crate) fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub(crate) fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context.push(context.into());
+        self
+    }
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}: ")?;
+        }
+
+        write!(f, "line {}:{}: {}", self.line, self.column, self.message)?;
+
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.join(" or "))?;
+        }
+
+        for context in &self.context {
+            write!(f, " ({context})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ScriptError {}
@@ -0,0 +1,298 @@
+use std::ops::Range;
+
+use chumsky::prelude::*;
+
+use crate::witness::windows_types::VirtualKeyCode;
+
+/// A mouse button, sent to the game as window messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+}
+
+/// What pressing a key of the script does in the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Holds the key down until it is released
+    Press(VirtualKeyCode),
+    Release(VirtualKeyCode),
+    /// Presses the key for a single tick
+    Tap(VirtualKeyCode),
+    /// Clicks for a single tick
+    Click(MouseButton),
+}
+
+/// What a key acts on in the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    Keyboard(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl KeyAction {
+    pub fn input(&self) -> Input {
+        match *self {
+            KeyAction::Press(code) | KeyAction::Release(code) | KeyAction::Tap(code) => {
+                Input::Keyboard(code)
+            }
+            KeyAction::Click(button) => Input::Mouse(button),
+        }
+    }
+}
+
+/// A key of the buttons field, with the token it is written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub token: &'static str,
+    pub action: KeyAction,
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.token)
+    }
+}
+
+/// A key pressed by a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+    pub key: Key,
+    /// The number of ticks after which the key is released. Taps and clicks
+    /// last one tick by default, and pressed keys are held until released.
+    pub hold: Option<u32>,
+}
+
+impl std::fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.hold {
+            Some(hold) => write!(f, "{}:{hold}", self.key),
+            None => write!(f, "{}", self.key),
+        }
+    }
+}
+
+const fn key(token: &'static str, action: KeyAction) -> Key {
+    Key { token, action }
+}
+
+/// All the keys a script can use. Single letters are the original keys,
+/// the other keys use their name between brackets and were added in version 1.
+/// To support a new key, add it here.
+pub const KEYS: &[Key] = &[
+    // Movement
+    key("U", KeyAction::Press(VirtualKeyCode::W)),
+    key("u", KeyAction::Release(VirtualKeyCode::W)),
+    key("D", KeyAction::Press(VirtualKeyCode::S)),
+    key("d", KeyAction::Release(VirtualKeyCode::S)),
+    key("L", KeyAction::Press(VirtualKeyCode::A)),
+    key("l", KeyAction::Release(VirtualKeyCode::A)),
+    key("R", KeyAction::Press(VirtualKeyCode::D)),
+    key("r", KeyAction::Release(VirtualKeyCode::D)),
+    // Sprint
+    key("S", KeyAction::Press(VirtualKeyCode::LShift)),
+    key("s", KeyAction::Release(VirtualKeyCode::LShift)),
+    // Toggle puzzle
+    key("P", KeyAction::Click(MouseButton::Left)),
+    key("p", KeyAction::Click(MouseButton::Right)),
+    // Menus
+    key("[ESC]", KeyAction::Tap(VirtualKeyCode::ESC)),
+    key("[SPACE]", KeyAction::Tap(VirtualKeyCode::Space)),
+    key("[TAB]", KeyAction::Tap(VirtualKeyCode::Tab)),
+    key("[ENTER]", KeyAction::Tap(VirtualKeyCode::Return)),
+];
+
+impl Key {
+    pub fn from_token(token: &str) -> Option<Key> {
+        KEYS.iter().find(|key| key.token == token).copied()
+    }
+
+    /// Parser for the buttons field. Keys are split after reading the whole field,
+    /// so that unknown keys are reported instead of ending the field.
+    pub(super) fn parser(
+        version: u64,
+    ) -> impl Parser<char, Vec<KeyPress>, Error = Simple<char>> + Clone {
+        // Long names and holds were added in version 1
+        let allowed =
+            move |c: &char| c.is_ascii_alphanumeric() || (version > 0 && "[]:".contains(*c));
+
+        filter(allowed)
+            .repeated()
+            .collect::<String>()
+            .try_map(move |field, span: Range<usize>| {
+                let mut keys = vec![];
+                let mut chars = field.char_indices().peekable();
+
+                while let Some((start, c)) = chars.next() {
+                    let mut end = start + 1;
+                    if c == '[' {
+                        while chars.next_if(|(_, c)| *c != ']').is_some() {
+                            end += 1;
+                        }
+                        if chars.next().is_none() {
+                            return Err(Simple::custom(
+                                span.start + start..span.start + end,
+                                "Unclosed `[` in the buttons field",
+                            ));
+                        }
+                        end += 1;
+                    }
+
+                    let token = &field[start..end];
+                    let key = Key::from_token(token).ok_or_else(|| {
+                        let available: Vec<_> = KEYS
+                            .iter()
+                            .filter(|key| version > 0 || key.token.len() == 1)
+                            .map(|key| key.token)
+                            .collect();
+                        Simple::custom(
+                            span.start + start..span.start + end,
+                            format!(
+                                "Unknown key `{token}`, available keys: {}",
+                                available.join(" ")
+                            ),
+                        )
+                    })?;
+
+                    // The hold duration
+                    let mut hold = None;
+                    if chars.next_if(|(_, c)| *c == ':').is_some() {
+                        let mut digits = String::new();
+                        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                            digits.push(c);
+                        }
+                        let hold_span = span.start + start..span.start + end + 1 + digits.len();
+
+                        let ticks = match digits.parse::<u32>() {
+                            Ok(0) => Err("Keys must be held for at least 1 tick".to_string()),
+                            Ok(ticks) => Ok(ticks),
+                            Err(_) => Err(format!(
+                                "Expected the number of ticks to hold `{token}` for, like `{token}:12`"
+                            )),
+                        };
+                        let ticks = ticks.map_err(|msg| Simple::custom(hold_span.clone(), msg))?;
+
+                        if let KeyAction::Release(_) = key.action {
+                            return Err(Simple::custom(
+                                hold_span,
+                                format!("`{token}` releases a key, it cannot be held"),
+                            ));
+                        }
+                        hold = Some(ticks);
+                    }
+
+                    keys.push(KeyPress { key, hold });
+                }
+
+                Ok(keys)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyAction, MouseButton};
+    use crate::script::Script;
+    use crate::witness::windows_types::VirtualKeyCode;
+
+    #[test]
+    fn test_keys() {
+        let src = "version 1\nstart now\n1>UP[ESC]\n+1>[SPACE]u\n";
+        let script = Script::try_from(src.to_string()).unwrap();
+        let actions: Vec<Vec<_>> = script
+            .lines
+            .iter()
+            .map(|line| line.keys.iter().map(|press| press.key.action).collect())
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                vec![
+                    KeyAction::Press(VirtualKeyCode::W),
+                    KeyAction::Click(MouseButton::Left),
+                    KeyAction::Tap(VirtualKeyCode::ESC),
+                ],
+                vec![
+                    KeyAction::Tap(VirtualKeyCode::Space),
+                    KeyAction::Release(VirtualKeyCode::W),
+                ],
+            ]
+        );
+        assert_eq!(script.to_string(), src);
+
+        let error = |src: &str| Script::try_from(src.to_string()).unwrap_err()[0].to_string();
+        assert_eq!(
+            error("version 1\nstart now\n1>UX\n"),
+            "line 3:4: Unknown key `X`, available keys: U u D d L l R r S s P p [ESC] [SPACE] [TAB] [ENTER]"
+        );
+        assert_eq!(
+            error("version 1\nstart now\n1>[JUMP]\n"),
+            "line 3:3: Unknown key `[JUMP]`, available keys: U u D d L l R r S s P p [ESC] [SPACE] [TAB] [ENTER]"
+        );
+
+        assert_eq!(
+            error("version 1\nstart now\n1>U[ESC\n"),
+            "line 3:4: Unclosed `[` in the buttons field"
+        );
+
+        // Long names only exist since version 1
+        assert!(Script::try_from("version 0\nstart now\n1>[ESC]\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_hold() {
+        let src = "version 1\nstart now\n1>U:12P:3\n+4>[ESC]:2 // menu\n";
+        let script = Script::try_from(src.to_string()).unwrap();
+        let keys: Vec<Vec<_>> = script
+            .lines
+            .iter()
+            .map(|line| {
+                line.keys
+                    .iter()
+                    .map(|press| (press.key.token, press.hold))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                vec![("U", Some(12)), ("P", Some(3))],
+                vec![("[ESC]", Some(2))],
+            ]
+        );
+        assert_eq!(script.to_string(), src);
+
+        let error = |src: &str| {
+            Script::try_from(format!("version 1\nstart now\n{src}")).unwrap_err()[0].to_string()
+        };
+        assert_eq!(
+            error("1>U:10\n+5>U:3\n"),
+            "line 4:1: `U` is still held by a previous line until tick 11"
+        );
+        assert_eq!(
+            error("repeat 2 {\n    +1>P:2\n}\n"),
+            "line 4:5: `P` is still held by a previous line until tick 3 (in repeat block at line 3, iteration 2)"
+        );
+        assert_eq!(
+            error("1>u:5\n"),
+            "line 3:3: `u` releases a key, it cannot be held"
+        );
+        assert_eq!(
+            error("1>RU:0\n"),
+            "line 3:4: Keys must be held for at least 1 tick"
+        );
+        assert_eq!(
+            error("1>U:\n"),
+            "line 3:3: Expected the number of ticks to hold `U` for, like `U:12`"
+        );
+        assert_eq!(
+            error("10>U:4294967290\n"),
+            "line 3:1: `U` is held for 4294967290 ticks, past the largest tick"
+        );
+
+        // Holds that follow each other are fine, as well as holds of different keys
+        assert!(Script::try_from("version 1\nstart now\n1>U:10D:2\n+10>U:1\n".to_string()).is_ok());
+        // Holds only exist since version 1
+        assert!(Script::try_from("version 0\nstart now\n1>U:12\n".to_string()).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{KeyAction, MouseButton, Script, ScriptError, ScriptLine, Severity, Statement};
+use crate::witness::windows_types::VirtualKeyCode;
+
+/// The kinds of mistakes found by the linter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LintCategory {
+    /// A key pressed while already held, or released while not held
+    RedundantKey,
+    /// A mouse movement that keeps going until the end of the script or through a wait
+    MouseNotReset,
+    /// Both mouse buttons down on the same tick
+    ConflictingClicks,
+    /// A tool that cannot be used in a legitimate run
+    NonLegitTool,
+}
+
+impl LintCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintCategory::RedundantKey => "redundant-key",
+            LintCategory::MouseNotReset => "mouse-not-reset",
+            LintCategory::ConflictingClicks => "conflicting-clicks",
+            LintCategory::NonLegitTool => "non-legit-tool",
+        }
+    }
+}
+
+impl Display for LintCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A likely mistake in a script that parses fine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LintWarning {
+    pub category: LintCategory,
+    /// The message and location of the warning, with a warning severity
+    pub warning: ScriptError,
+}
+
+impl Display for LintWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.warning, self.category)
+    }
+}
+
+/// Looks for the source of an included file in `statements`
+fn included_src<'a>(statements: &'a [Statement], path: &Path) -> Option<&'a str> {
+    statements.iter().find_map(|statement| match statement {
+        Statement::Include {
+            file: Some(file), ..
+        } => {
+            if file.path == path {
+                Some(file.src.as_str())
+            } else {
+                included_src(&file.statements, path)
+            }
+        }
+        Statement::Repeat { body, .. } | Statement::Macro { body, .. } => included_src(body, path),
+        _ => None,
+    })
+}
+
+struct Linter<'a> {
+    script: &'a Script,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter<'_> {
+    /// Adds a warning located at `line`. Lines of repeat blocks and macros
+    /// are only reported the first time they are played.
+    fn warn(
+        &mut self,
+        category: LintCategory,
+        line: &ScriptLine,
+        message: String,
+        hint: Option<String>,
+    ) {
+        let src = match &line.file {
+            Some(file) => included_src(&self.script.statements, file).unwrap_or(&self.script.src),
+            None => &self.script.src,
+        };
+        let mut warning = ScriptError::new(message, line.span.clone(), src, line.file.as_deref());
+        warning.severity = Severity::Warning;
+        if let Some(hint) = hint {
+            warning = warning.with_hint(hint);
+        }
+
+        let reported = self.warnings.iter().any(|lint| {
+            lint.category == category
+                && lint.warning.file == warning.file
+                && lint.warning.span == warning.span
+                && lint.warning.message == warning.message
+        });
+        if !reported {
+            self.warnings.push(LintWarning { category, warning });
+        }
+    }
+}
+
+impl Script {
+    /// Looks for mistakes that the parser accepts, but that likely make the script desync.
+    /// The tools that are not legitimate are only reported in legit scripts.
+    pub fn lint(&self) -> Vec<LintWarning> {
+        self.lint_as(self.metadata.legit)
+    }
+
+    /// Lints the script, reporting the tools that are not legitimate if `legit` is set
+    fn lint_as(&self, legit: bool) -> Vec<LintWarning> {
+        let mut linter = Linter {
+            script: self,
+            warnings: vec![],
+        };
+
+        // Keys held down, with the tick they are released at if it is known
+        let mut held: HashMap<VirtualKeyCode, Option<u32>> = HashMap::new();
+        // Mouse buttons down, with the tick they are released at
+        let mut clicks: HashMap<MouseButton, u32> = HashMap::new();
+        // The line that set the current mouse movement, if the mouse is moving
+        let mut moving: Option<&ScriptLine> = None;
+
+        for line in &self.lines {
+            let tick = line.absolute_tick;
+            held.retain(|_, release| release.is_none_or(|release| release > tick));
+            clicks.retain(|_, release| *release > tick);
+
+            if let Some(moving) = moving.filter(|_| !line.waits.is_empty()) {
+                let (x, y) = moving.mouse.unwrap_or_default();
+                linter.warn(
+                    LintCategory::MouseNotReset,
+                    moving,
+                    format!("The mouse keeps moving by {x} {y} during the wait that follows"),
+                    Some(
+                        "Waits do not always last as long, reset the mouse to `0 0` before it"
+                            .into(),
+                    ),
+                );
+            }
+
+            for press in &line.keys {
+                let token = press.key.token;
+                match press.key.action {
+                    KeyAction::Press(code) => {
+                        if held.contains_key(&code) {
+                            linter.warn(
+                                LintCategory::RedundantKey,
+                                line,
+                                format!("`{token}` presses a key that is already held"),
+                                None,
+                            );
+                        }
+                        held.insert(code, press.hold.map(|hold| tick + hold));
+                    }
+                    KeyAction::Release(code) => {
+                        if held.remove(&code).is_none() {
+                            linter.warn(
+                                LintCategory::RedundantKey,
+                                line,
+                                format!("`{token}` releases a key that is not held"),
+                                None,
+                            );
+                        }
+                    }
+                    KeyAction::Tap(_) => {}
+                    KeyAction::Click(button) => {
+                        let other = match button {
+                            MouseButton::Left => MouseButton::Right,
+                            MouseButton::Right => MouseButton::Left,
+                        };
+                        if clicks.contains_key(&other) {
+                            linter.warn(
+                                LintCategory::ConflictingClicks,
+                                line,
+                                "Both mouse buttons are down on the same tick".to_string(),
+                                Some("`P` and `p` cancel each other, only use one".to_string()),
+                            );
+                        }
+                        clicks.insert(button, tick + press.hold.unwrap_or(1));
+                    }
+                }
+            }
+
+            if let Some(mouse) = line.mouse {
+                moving = (mouse != (0, 0)).then_some(line);
+            }
+
+            for tool in line.tools.iter().flatten() {
+                if legit && !tool.is_legit() {
+                    linter.warn(
+                        LintCategory::NonLegitTool,
+                        line,
+                        format!("`{}` is not legitimate", tool.name()),
+                        Some(
+                            "It is only meant for segmenting, remove it from the final run".into(),
+                        ),
+                    );
+                }
+            }
+        }
+
+        if let Some(moving) = moving {
+            let (x, y) = moving.mouse.unwrap_or_default();
+            linter.warn(
+                LintCategory::MouseNotReset,
+                moving,
+                format!("The mouse keeps moving by {x} {y} until the end of the script"),
+                Some("Reset it with a line that sets the look angles to `0 0`".to_string()),
+            );
+        }
+
+        linter.warnings
+    }
+
+    /// The uses of the tools that are not legitimate, as errors.
+    /// Legit scripts cannot have any of them.
+    pub fn legit_errors(&self) -> Vec<ScriptError> {
+        self.lint_as(true)
+            .into_iter()
+            .filter(|lint| lint.category == LintCategory::NonLegitTool)
+            .map(|lint| ScriptError {
+                severity: Severity::Error,
+                ..lint
+                    .warning
+                    .with_hint("Legit runs cannot use the tools that move the player or the camera")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LintCategory;
+    use crate::script::{Script, Severity};
+
+    fn lint(body: &str) -> Vec<(LintCategory, usize, String)> {
+        let script = Script::try_from(format!("version 1\nstart now\n{body}")).unwrap();
+        script
+            .lint()
+            .into_iter()
+            .map(|lint| (lint.category, lint.warning.line, lint.warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(lint("1>U|10 0\n+5>|0 0\n+10>u\n+1>P\n"), vec![]);
+
+        assert_eq!(
+            lint("1>U\n+5>U\n+1>ud\n"),
+            vec![
+                (
+                    LintCategory::RedundantKey,
+                    4,
+                    "`U` presses a key that is already held".to_string()
+                ),
+                (
+                    LintCategory::RedundantKey,
+                    5,
+                    "`d` releases a key that is not held".to_string()
+                ),
+            ]
+        );
+        // A held key is released by itself
+        assert_eq!(lint("1>U:5\n+5>U\n+1>u\n"), vec![]);
+
+        assert_eq!(
+            lint("1>|10 0\n+5>|20 0\n"),
+            vec![(
+                LintCategory::MouseNotReset,
+                4,
+                "The mouse keeps moving by 20 0 until the end of the script".to_string()
+            )]
+        );
+        assert_eq!(
+            lint("1>|10 0\nwait puzzle_unlock\n+5>|0 0\n"),
+            vec![(
+                LintCategory::MouseNotReset,
+                3,
+                "The mouse keeps moving by 10 0 during the wait that follows".to_string()
+            )]
+        );
+        // Interpolations stop the mouse by themselves
+        assert_eq!(lint("1..10>|linear(100, 0)\n"), vec![]);
+
+        assert_eq!(
+            lint("1>Pp\n+5>P:3\n+2>p\n+1>p\n"),
+            vec![
+                (
+                    LintCategory::ConflictingClicks,
+                    3,
+                    "Both mouse buttons are down on the same tick".to_string()
+                ),
+                (
+                    LintCategory::ConflictingClicks,
+                    5,
+                    "Both mouse buttons are down on the same tick".to_string()
+                ),
+            ]
+        );
+
+        // Lines of repeat blocks are only reported once
+        assert_eq!(
+            lint("1>U\nrepeat 3 {\n    +1>U\n}\n"),
+            vec![(
+                LintCategory::RedundantKey,
+                5,
+                "`U` presses a key that is already held".to_string()
+            )]
+        );
+
+        // The tools for segmenting are fine in scripts that are not meant to be legit
+        assert_eq!(
+            lint("1>||setpos 1 2 3 0 0; lookat 1 2 3; setang 0 0\n"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_legit() {
+        let src = "version 1
+legit
+start now
+1>||setpos 1 2 3 0 0
++1>||lookat 1 2 3; setang 0 0; assert unlocked
+";
+        let errors = Script::try_from(src.to_string()).unwrap_err();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.severity, error.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    Severity::Error,
+                    "line 4:1: `setpos` is not legitimate".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "line 5:1: `lookat` is not legitimate".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "line 5:1: `setang` is not legitimate".to_string()
+                ),
+            ]
+        );
+
+        // The legit mode of the playback refuses them without the directive
+        let script =
+            Script::try_from("version 1\nstart now\n1>||setang 0 0\n".to_string()).unwrap();
+        assert!(script.lint().is_empty());
+        assert_eq!(
+            script.legit_errors()[0].to_string(),
+            "line 3:1: `setang` is not legitimate"
+        );
+
+        // Assertions do not change the game
+        let src = "version 1\nlegit\nstart now\n1>U||assert unlocked\n+5>u\n";
+        assert!(Script::try_from(src.to_string()).is_ok());
+    }
+}
@@ -0,0 +1,387 @@
+use std::ops::Range;
+
+use chumsky::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Script, Severity, Statement};
+
+/// Informations about how a script was made, given by the directives of its header.
+/// All of them are optional.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Width and height of the game window
+    pub resolution: Option<(u32, u32)>,
+    pub fov: Option<f32>,
+    pub mouse_sensitivity: Option<f32>,
+    pub author: Option<String>,
+    pub category: Option<String>,
+    pub game_version: Option<String>,
+    /// The script is meant to be submitted, so it cannot use the tools that are not legitimate
+    pub legit: bool,
+}
+
+/// The settings of the running game. Settings that could not be read are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameSettings {
+    pub resolution: Option<(u32, u32)>,
+    pub fov: Option<f32>,
+    pub mouse_sensitivity: Option<f32>,
+    pub game_version: Option<String>,
+}
+
+/// The result of comparing a setting of the script with the game.
+/// Errors prevent the script from playing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingCheck {
+    pub severity: Severity,
+    pub message: String,
+}
+
+enum Directive {
+    Resolution(u32, u32),
+    Fov(f32),
+    MouseSensitivity(f32),
+    Author(String),
+    Category(String),
+    GameVersion(String),
+    Legit,
+}
+
+enum HeaderItem {
+    Directive(Directive, Range<usize>),
+    Comment(String),
+}
+
+impl Directive {
+    fn name(&self) -> &'static str {
+        match self {
+            Directive::Resolution(..) => "resolution",
+            Directive::Fov(_) => "fov",
+            Directive::MouseSensitivity(_) => "mouse_sensitivity",
+            Directive::Author(_) => "author",
+            Directive::Category(_) => "category",
+            Directive::GameVersion(_) => "game_version",
+            Directive::Legit => "legit",
+        }
+    }
+}
+
+impl Metadata {
+    fn set(&mut self, directive: Directive) {
+        match directive {
+            Directive::Resolution(width, height) => self.resolution = Some((width, height)),
+            Directive::Fov(fov) => self.fov = Some(fov),
+            Directive::MouseSensitivity(sensitivity) => self.mouse_sensitivity = Some(sensitivity),
+            Directive::Author(author) => self.author = Some(author),
+            Directive::Category(category) => self.category = Some(category),
+            Directive::GameVersion(version) => self.game_version = Some(version),
+            Directive::Legit => self.legit = true,
+        }
+    }
+
+    fn is_set(&self, directive: &Directive) -> bool {
+        match directive {
+            Directive::Resolution(..) => self.resolution.is_some(),
+            Directive::Fov(_) => self.fov.is_some(),
+            Directive::MouseSensitivity(_) => self.mouse_sensitivity.is_some(),
+            Directive::Author(_) => self.author.is_some(),
+            Directive::Category(_) => self.category.is_some(),
+            Directive::GameVersion(_) => self.game_version.is_some(),
+            Directive::Legit => self.legit,
+        }
+    }
+
+    /// The header lines of the directives, in the canonical order
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+
+        if let Some((width, height)) = self.resolution {
+            lines.push(format!("resolution {width}x{height}"));
+        }
+        if let Some(fov) = self.fov {
+            lines.push(format!("fov {fov}"));
+        }
+        if let Some(sensitivity) = self.mouse_sensitivity {
+            lines.push(format!("mouse_sensitivity {sensitivity}"));
+        }
+        if let Some(author) = &self.author {
+            lines.push(format!("author \"{author}\""));
+        }
+        if let Some(category) = &self.category {
+            lines.push(format!("category \"{category}\""));
+        }
+        if let Some(version) = &self.game_version {
+            lines.push(format!("game_version \"{version}\""));
+        }
+        if self.legit {
+            lines.push("legit".to_string());
+        }
+
+        lines
+    }
+
+    /// Compares the metadata with the settings of the game.
+    ///
+    /// A different resolution, fov or sensitivity changes what the mouse movements do,
+    /// so they are errors. A different game version, and the settings that cannot be
+    /// read from the game, are warnings.
+    pub fn check(&self, settings: &GameSettings) -> Vec<SettingCheck> {
+        fn check<T: PartialEq + std::fmt::Display>(
+            name: &str,
+            expected: Option<T>,
+            found: Option<T>,
+            severity: Severity,
+            checks: &mut Vec<SettingCheck>,
+        ) {
+            match (expected, found) {
+                (Some(expected), None) => checks.push(SettingCheck {
+                    severity: Severity::Warning,
+                    message: format!(
+                        "Cannot verify the {name}, as it cannot be read from the game. \
+                        Make sure it is {expected}"
+                    ),
+                }),
+                (Some(expected), Some(found)) if expected != found => checks.push(SettingCheck {
+                    severity,
+                    message: format!(
+                        "The script expects a {name} of {expected}, the game uses {found}"
+                    ),
+                }),
+                _ => {}
+            }
+        }
+
+        let resolution =
+            |res: Option<(u32, u32)>| res.map(|(width, height)| format!("{width}x{height}"));
+
+        let mut checks = vec![];
+        check(
+            "resolution",
+            resolution(self.resolution),
+            resolution(settings.resolution),
+            Severity::Error,
+            &mut checks,
+        );
+        check("fov", self.fov, settings.fov, Severity::Error, &mut checks);
+        check(
+            "mouse sensitivity",
+            self.mouse_sensitivity,
+            settings.mouse_sensitivity,
+            Severity::Error,
+            &mut checks,
+        );
+        check(
+            "game version",
+            self.game_version.as_ref(),
+            settings.game_version.as_ref(),
+            Severity::Warning,
+            &mut checks,
+        );
+
+        checks
+    }
+}
+
+impl Script {
+    /// Parser for the header lines between the version and the start line.
+    /// The comments are returned separately, to be moved to the start of the body.
+    pub(super) fn header_parser(
+        version: u64,
+    ) -> impl Parser<char, (Metadata, Vec<Statement>), Error = Simple<char>> {
+        let comment = Self::comment_parser().map(HeaderItem::Comment);
+
+        // Directives were added in version 1
+        let item = match version {
+            0 => comment.boxed(),
+            _ => comment
+                .or(Self::directive_parser().map_with_span(HeaderItem::Directive))
+                .boxed(),
+        };
+
+        item.padded().repeated().validate(|items, _, emit| {
+            let mut metadata = Metadata::default();
+            let mut comments = vec![];
+
+            for item in items {
+                match item {
+                    HeaderItem::Directive(directive, span) => {
+                        if metadata.is_set(&directive) {
+                            emit(Simple::custom(
+                                span,
+                                format!("Duplicate `{}` directive", directive.name()),
+                            ));
+                        }
+                        metadata.set(directive);
+                    }
+                    HeaderItem::Comment(text) => comments.push(Statement::Comment {
+                        text,
+                        inline: false,
+                    }),
+                }
+            }
+
+            (metadata, comments)
+        })
+    }
+
+    fn directive_parser() -> impl Parser<char, Directive, Error = Simple<char>> {
+        let padding = filter(|c: &char| *c == ' ' || *c == '\t')
+            .repeated()
+            .at_least(1);
+
+        let int = text::int(10).try_map(|s: String, span| {
+            s.parse::<u32>()
+                .map_err(|err| Simple::custom(span, err.to_string()))
+        });
+
+        let float = text::int(10)
+            .chain::<char, _, _>(
+                just('.')
+                    .chain::<char, _, _>(text::digits(10).or_not())
+                    .or_not()
+                    .map(|fract| fract.unwrap_or_default()),
+            )
+            .collect::<String>()
+            .map(|s| s.parse::<f32>().unwrap());
+
+        let directive = |name| text::keyword(name).then_ignore(padding);
+
+        directive("resolution")
+            .ignore_then(int.then_ignore(just('x')).then(int))
+            .map(|(width, height)| Directive::Resolution(width, height))
+            .or(directive("fov").ignore_then(float).map(Directive::Fov))
+            .or(directive("mouse_sensitivity")
+                .ignore_then(float)
+                .map(Directive::MouseSensitivity))
+            .or(directive("author")
+                .ignore_then(Self::quoted_parser())
+                .map(Directive::Author))
+            .or(directive("category")
+                .ignore_then(Self::quoted_parser())
+                .map(Directive::Category))
+            .or(directive("game_version")
+                .ignore_then(Self::quoted_parser())
+                .map(Directive::GameVersion))
+            .or(text::keyword("legit").map(|_| Directive::Legit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{GameSettings, Metadata, Script, Severity};
+
+    #[test]
+    fn test_metadata() {
+        let src = "version 1
+// Made for the any% route
+resolution 1920x1080
+fov 84
+mouse_sensitivity 0.5 // default
+author \"Phoenixx\"
+category \"Any%\"
+game_version \"2017\"
+legit
+start newgame
+1>U
+";
+        let script = Script::try_from(src.to_string()).unwrap();
+        assert_eq!(
+            script.metadata,
+            Metadata {
+                resolution: Some((1920, 1080)),
+                fov: Some(84.),
+                mouse_sensitivity: Some(0.5),
+                author: Some("Phoenixx".to_string()),
+                category: Some("Any%".to_string()),
+                game_version: Some("2017".to_string()),
+                legit: true,
+            }
+        );
+
+        // Comments are moved to the body, the directives keep their order
+        assert_eq!(
+            script.to_string(),
+            "version 1
+resolution 1920x1080
+fov 84
+mouse_sensitivity 0.5
+author \"Phoenixx\"
+category \"Any%\"
+game_version \"2017\"
+legit
+start newgame
+// Made for the any% route
+// default
+1>U
+"
+        );
+
+        let errors =
+            Script::try_from("version 1\nfov 84\nfov 90\nstart now\n1>\n".to_string()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (3, 1));
+        assert_eq!(errors[0].message, "Duplicate `fov` directive");
+
+        // Directives only exist since version 1
+        assert!(Script::try_from("version 0\nfov 84\nstart now\n1>\n".to_string()).is_err());
+        assert!(Script::try_from("version 1\nfov high\nstart now\n1>\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let metadata = Metadata {
+            resolution: Some((1920, 1080)),
+            fov: Some(84.),
+            author: Some("Phoenixx".to_string()),
+            game_version: Some("2017".to_string()),
+            ..Default::default()
+        };
+
+        let settings = GameSettings {
+            resolution: Some((1920, 1080)),
+            fov: Some(84.),
+            mouse_sensitivity: Some(1.),
+            game_version: Some("2017".to_string()),
+        };
+        assert!(metadata.check(&settings).is_empty());
+        assert!(Metadata::default().check(&settings).is_empty());
+
+        let checks = metadata.check(&GameSettings {
+            resolution: Some((1280, 720)),
+            fov: None,
+            game_version: Some("2016".to_string()),
+            ..settings
+        });
+        let checks: Vec<_> = checks
+            .iter()
+            .map(|check| (check.severity, check.message.as_str()))
+            .collect();
+        assert_eq!(
+            checks,
+            vec![
+                (
+                    Severity::Error,
+                    "The script expects a resolution of 1920x1080, the game uses 1280x720"
+                ),
+                (
+                    Severity::Warning,
+                    "Cannot verify the fov, as it cannot be read from the game. Make sure it is 84"
+                ),
+                (
+                    Severity::Warning,
+                    "The script expects a game version of 2017, the game uses 2016"
+                ),
+            ]
+        );
+
+        // The settings the game does not tell do not stop the script
+        let checks = metadata.check(&GameSettings {
+            resolution: Some((1920, 1080)),
+            ..Default::default()
+        });
+        assert!(checks
+            .iter()
+            .all(|check| check.severity == Severity::Warning));
+        assert_eq!(checks.len(), 2);
+    }
+}
@@ -0,0 +1,2289 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use chumsky::{prelude::*, text::Character};
+use serde::{Deserialize, Serialize};
+
+use crate::witness::witness_types::{InteractionStatus, Vec2, Vec3};
+
+mod curve;
+mod error;
+mod keys;
+mod lint;
+mod metadata;
+mod printer;
+mod upgrade;
+pub use curve::Curve;
+pub use error::{ScriptError, Severity};
+pub use keys::{Input, Key, KeyAction, KeyPress, MouseButton, KEYS};
+pub use lint::{LintCategory, LintWarning};
+pub use metadata::{GameSettings, Metadata, SettingCheck};
+pub use printer::{PrintOptions, TickStyle};
+
+/// The latest version of the script format
+pub const LATEST_VERSION: u64 = 1;
+
+/// The most times a block can be repeated
+const MAX_REPEAT: u32 = 10_000;
+/// The most lines a script can expand into, as nested repeat blocks multiply them
+const MAX_LINES: usize = 1_000_000;
+
+/// Defines how the TAS should start.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartType {
+    /// TAS should start immediately
+    Now,
+    /// TAS should start from a new game
+    NewGame,
+    /// TAS should start from the given save
+    Save(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tool {
+    /// Set the position of the player
+    SetPos { pos: Vec3, ang: Vec2 },
+    /// Set the angles of the camera
+    SetAng { ang: Vec2 },
+    /// Turn the camera towards a point, from the current position of the player
+    LookAt { target: Vec3 },
+    /// Check the state of the game, to detect desyncs
+    Assert(Assertion),
+}
+
+/// A check of the state of the game during playback
+#[derive(Debug, Clone, PartialEq)]
+pub enum Assertion {
+    /// The player is at most `within` away from `pos`
+    Position { pos: Vec3, within: f32 },
+    /// A puzzle unlocked since the previous line
+    Unlocked,
+}
+
+impl Tool {
+    /// The name of the tool in scripts
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::SetPos { .. } => "setpos",
+            Tool::SetAng { .. } => "setang",
+            Tool::LookAt { .. } => "lookat",
+            Tool::Assert(_) => "assert",
+        }
+    }
+
+    /// Whether the tool can be used in a legitimate run. The tools that move the player
+    /// or the camera are only meant for segmenting, while assertions change nothing.
+    pub fn is_legit(&self) -> bool {
+        matches!(self, Tool::Assert(_))
+    }
+
+    /// The call that builds the tool, to write it back in a script
+    fn to_call(&self) -> ToolCall {
+        let number = |value: f32| Expr::Literal(value.to_string());
+        let word = |word: &str| Expr::Param(word.to_string());
+        let args = match self {
+            Tool::SetPos { pos, ang } => [pos.x, pos.y, pos.z, ang.x, ang.y].map(number).to_vec(),
+            Tool::SetAng { ang } => [ang.x, ang.y].map(number).to_vec(),
+            Tool::LookAt { target } => [target.x, target.y, target.z].map(number).to_vec(),
+            Tool::Assert(Assertion::Position { pos, within }) => vec![
+                word("pos"),
+                number(pos.x),
+                number(pos.y),
+                number(pos.z),
+                word("within"),
+                number(*within),
+            ],
+            Tool::Assert(Assertion::Unlocked) => vec![word("unlocked")],
+        };
+
+        ToolCall {
+            name: self.name().to_string(),
+            args,
+            span: 0..0,
+        }
+    }
+
+    /// Builds the tool from its call in the script
+    fn from_call(call: &ToolCall, env: &HashMap<String, f64>) -> Result<Self, String> {
+        if call.name == "assert" {
+            return Self::assertion_from_call(call, env);
+        }
+
+        let args = call
+            .args
+            .iter()
+            .map(|arg| arg.eval_f32(env))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let expect_args = |count: usize| {
+            if args.len() == count {
+                Ok(())
+            } else {
+                Err(format!(
+                    "Tool `{}` expects {count} arguments, got {}",
+                    call.name,
+                    args.len()
+                ))
+            }
+        };
+
+        match call.name.as_str() {
+            "setpos" => {
+                expect_args(5)?;
+                Ok(Tool::SetPos {
+                    pos: Vec3 {
+                        x: args[0],
+                        y: args[1],
+                        z: args[2],
+                    },
+                    ang: Vec2 {
+                        x: args[3],
+                        y: args[4],
+                    },
+                })
+            }
+            "setang" => {
+                expect_args(2)?;
+                Ok(Tool::SetAng {
+                    ang: Vec2 {
+                        x: args[0],
+                        y: args[1],
+                    },
+                })
+            }
+            "lookat" => {
+                expect_args(3)?;
+                Ok(Tool::LookAt {
+                    target: Vec3 {
+                        x: args[0],
+                        y: args[1],
+                        z: args[2],
+                    },
+                })
+            }
+            name => Err(format!(
+                "Unknown tool `{name}`, available tools: setpos, setang, lookat, assert"
+            )),
+        }
+    }
+
+    /// Builds an assertion. Its arguments start with what is checked, as a word.
+    fn assertion_from_call(call: &ToolCall, env: &HashMap<String, f64>) -> Result<Self, String> {
+        let is_word = |arg: &Expr, word: &str| matches!(arg, Expr::Param(name) if name == word);
+
+        match &call.args[..] {
+            [what, x, y, z, within, distance]
+                if is_word(what, "pos") && is_word(within, "within") =>
+            {
+                Ok(Tool::Assert(Assertion::Position {
+                    pos: Vec3 {
+                        x: x.eval_f32(env)?,
+                        y: y.eval_f32(env)?,
+                        z: z.eval_f32(env)?,
+                    },
+                    within: distance.eval_f32(env)?,
+                }))
+            }
+            [what] if is_word(what, "unlocked") => Ok(Tool::Assert(Assertion::Unlocked)),
+            _ => Err(
+                "Expected `assert pos <x> <y> <z> within <distance>` or `assert unlocked`"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// An event of the game that a script can wait for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wait {
+    /// Wait for a puzzle to unlock
+    PuzzleUnlock,
+    /// Wait for the player to be in the given interaction status
+    Interaction(InteractionStatus),
+}
+
+impl Wait {
+    /// The interaction statuses, by their name in scripts
+    const INTERACTION_STATUSES: [(&'static str, InteractionStatus); 4] = [
+        ("FocusMode", InteractionStatus::FocusMode),
+        ("SolvingPanel", InteractionStatus::SolvingPanel),
+        ("Walking", InteractionStatus::Walking),
+        ("Cinematic", InteractionStatus::Cinematic),
+    ];
+
+    fn interaction_from_name(name: &str) -> Result<Self, String> {
+        Self::INTERACTION_STATUSES
+            .iter()
+            .find(|(status_name, _)| *status_name == name)
+            .map(|&(_, status)| Wait::Interaction(status))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::INTERACTION_STATUSES
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect();
+                format!(
+                    "Unknown interaction status `{name}`, expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+
+    pub(crate) fn interaction_name(status: InteractionStatus) -> &'static str {
+        Self::INTERACTION_STATUSES
+            .iter()
+            .find(|(_, s)| *s == status)
+            .map_or("", |(name, _)| name)
+    }
+}
+
+/// A number in the script, evaluated when the statements are expanded
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// A number, as written in the script
+    Literal(String),
+    /// The value of a variable or of a macro parameter
+    Param(String),
+    Neg(Box<Expr>),
+    /// An expression between parentheses
+    Paren(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Expr {
+    fn eval_param(name: &str, env: &HashMap<String, f64>) -> Result<f64, String> {
+        env.get(name)
+            .copied()
+            .ok_or_else(|| format!("Unknown variable or parameter `{name}`"))
+    }
+
+    fn eval(&self, env: &HashMap<String, f64>) -> Result<f64, String> {
+        match self {
+            Expr::Literal(num) => num.parse().map_err(|_| format!("Invalid number {num}")),
+            Expr::Param(name) => Self::eval_param(name, env),
+            Expr::Neg(expr) => Ok(-expr.eval(env)?),
+            Expr::Paren(expr) => expr.eval(env),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(env)?, rhs.eval(env)?);
+                match op {
+                    BinaryOp::Add => Ok(lhs + rhs),
+                    BinaryOp::Sub => Ok(lhs - rhs),
+                    BinaryOp::Mul => Ok(lhs * rhs),
+                    BinaryOp::Div if rhs == 0.0 => Err("Division by zero".to_string()),
+                    BinaryOp::Div => Ok(lhs / rhs),
+                }
+            }
+        }
+    }
+
+    fn eval_i32(&self, env: &HashMap<String, f64>) -> Result<i32, String> {
+        // Literals are parsed directly to avoid any rounding
+        let value = match self {
+            Expr::Literal(num) => {
+                return num
+                    .parse()
+                    .map_err(|_| format!("Expected integer, got {num}"))
+            }
+            expr => expr.eval(env)?,
+        };
+
+        if value.fract() != 0.0 || value < i32::MIN as f64 || value > i32::MAX as f64 {
+            return Err(format!("Expected integer, got {value}"));
+        }
+
+        Ok(value as i32)
+    }
+
+    fn eval_f32(&self, env: &HashMap<String, f64>) -> Result<f32, String> {
+        // Literals are parsed directly to avoid any double rounding
+        match self {
+            Expr::Literal(num) => num.parse().map_err(|_| format!("Invalid number {num}")),
+            expr => Ok(expr.eval(env)? as f32),
+        }
+    }
+}
+
+/// A tool as written in the script, before its arguments are evaluated
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub span: Range<usize>,
+}
+
+/// The mouse field of an input line
+#[derive(Debug, Clone)]
+pub enum Mouse {
+    /// A movement done on every tick, until it is changed
+    Move(Expr, Expr),
+    /// A total movement spread over the tick range of the line
+    Interpolate { curve: Curve, x: Expr, y: Expr },
+}
+
+/// An input line as written in the script
+#[derive(Debug, Clone)]
+pub struct InputLine {
+    pub relative: bool,
+    pub tick: u32,
+    /// The last tick of the range of an interpolated line. Like `tick`,
+    /// it is relative to the previous line if `relative` is set.
+    pub end: Option<(bool, u32)>,
+    pub keys: Vec<KeyPress>,
+    pub mouse: Option<Mouse>,
+    pub tools: Option<Vec<ToolCall>>,
+    pub span: Range<usize>,
+}
+
+/// A line to play, obtained by expanding the statements
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLine {
+    /// The tick as it is written in the script
+    pub relative: bool,
+    pub tick: u32,
+    /// The tick the line is played on
+    pub absolute_tick: u32,
+    pub keys: Vec<KeyPress>,
+    pub mouse: Option<(i32, i32)>,
+    pub tools: Option<Vec<Tool>>,
+    /// Location of the line in characters, in the file it comes from
+    pub span: Range<usize>,
+    /// The file the line is written in, if the script was loaded from a file
+    pub file: Option<PathBuf>,
+    /// Whether the line was added by the expansion instead of being written: the ticks
+    /// after the first of an interpolation, and the release of the mouse after it.
+    pub generated: bool,
+    /// The comments on their own line right before this line
+    pub comments: Vec<String>,
+    /// The comment at the end of the line
+    pub inline_comment: Option<String>,
+    /// The events to wait for before playing the line. The ticks of this line
+    /// and the following ones are counted from when the last one happened.
+    pub waits: Vec<Wait>,
+}
+
+/// A statement of the script body, as written in the file.
+#[derive(Debug, Clone)]
+pub enum Statement {
+    /// A single input line
+    Line(InputLine),
+    /// A block of statements played `count` times in a row.
+    /// The ticks inside the block are relative.
+    Repeat {
+        count: u32,
+        body: Vec<Statement>,
+        span: Range<usize>,
+    },
+    /// The definition of a macro. The ticks inside the body are relative.
+    Macro {
+        name: String,
+        params: Vec<String>,
+        body: Vec<Statement>,
+        span: Range<usize>,
+    },
+    /// A call to a macro, with the values of its parameters
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        span: Range<usize>,
+    },
+    /// A comment on its own line, or at the end of the previous statement if `inline`.
+    /// The text does not include the `//`.
+    Comment { text: String, inline: bool },
+    /// An empty line between two statements
+    Blank,
+    /// Stops the script until an event happens, the next line must be relative
+    Wait { wait: Wait, span: Range<usize> },
+    /// Names the position of the next line
+    Label { name: String, span: Range<usize> },
+    /// Defines a variable, usable until the end of the current block
+    Let {
+        name: String,
+        value: Expr,
+        span: Range<usize>,
+    },
+    /// Another file whose statements are inserted at this point.
+    /// `file` is filled once the file has been loaded.
+    Include {
+        path: String,
+        span: Range<usize>,
+        file: Option<Box<IncludedFile>>,
+    },
+}
+
+/// A script file included by another one. It only contains statements, no header.
+#[derive(Debug, Clone)]
+pub struct IncludedFile {
+    pub path: PathBuf,
+    pub src: String,
+    pub statements: Vec<Statement>,
+}
+
+/// A named position in the script, that the controller can skip to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    pub name: String,
+    /// Index of the line following the label
+    pub line: usize,
+    /// Tick of that line, without the time spent waiting
+    pub tick: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub version: u64,
+    pub metadata: Metadata,
+    pub start: StartType,
+    pub statements: Vec<Statement>,
+    /// The lines to play, obtained by expanding the statements
+    pub lines: Vec<ScriptLine>,
+    pub labels: Vec<Label>,
+    /// The source of the script, to locate its lines
+    pub src: String,
+    /// The file the script was read from
+    pub path: Option<PathBuf>,
+}
+
+impl Script {
+    fn get_parser() -> impl Parser<char, Self, Error = Simple<char>> {
+        let version = text::keyword("version")
+            .padded()
+            .ignore_then(text::int(10).try_map(|s: String, span| match s.parse() {
+                Ok(version) if version <= LATEST_VERSION => Ok(version),
+                _ => Err(Simple::custom(
+                    span,
+                    format!("Unsupported version {s}, the latest version is {LATEST_VERSION}"),
+                )),
+            }))
+            .padded();
+
+        // The rest of the script is parsed according to its version
+        version.then_with(|version| {
+            Self::header_parser(version)
+                .then(Self::start_parser(version))
+                .then(Self::statements_parser(version))
+                // Checked here to keep the errors of the statements
+                .then_ignore(end())
+                .map(move |(((metadata, comments), start), statements)| Script {
+                    version,
+                    metadata,
+                    start,
+                    // Comments in the header are moved to the start of the body
+                    statements: comments.into_iter().chain(statements).collect(),
+                    lines: vec![],
+                    labels: vec![],
+                    src: String::new(),
+                    path: None,
+                })
+        })
+    }
+
+    /// Parser for the start line
+    fn start_parser(version: u64) -> impl Parser<char, StartType, Error = Simple<char>> {
+        // Version 0 takes the rest of the line as the save name, later ones quote it
+        let save_name = match version {
+            0 => filter(|c: &char| !c.is_ascii_control())
+                .repeated()
+                .collect()
+                .boxed(),
+            _ => Self::quoted_parser().boxed(),
+        };
+
+        text::keyword("start").padded().ignore_then(
+            text::keyword("newgame")
+                .to(StartType::NewGame)
+                .or(text::keyword("now").to(StartType::Now))
+                .or(text::keyword("save")
+                    .padded()
+                    .ignore_then(save_name)
+                    .map(StartType::Save)),
+        )
+    }
+
+    /// Parser for a string between double quotes, on a single line
+    fn quoted_parser() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+        filter(|c: &char| *c != '"' && !c.is_ascii_control())
+            .repeated()
+            .collect()
+            .delimited_by(just('"'), just('"'))
+    }
+
+    /// Parser for the body of a script, also used for included files
+    fn statements_parser(version: u64) -> impl Parser<char, Vec<Statement>, Error = Simple<char>> {
+        let padding_no_newline = filter(|c: &char| c.is_inline_whitespace()).repeated();
+
+        let tick = just('+')
+            .or_not()
+            .then(text::int(10).try_map(|s: String, span| {
+                s.parse::<u32>()
+                    .map_err(|_| Simple::custom(span, format!("Tick {s} is too large")))
+            }));
+
+        let keys = Key::parser(version);
+
+        // Arithmetic was added in version 1. Spaces separate the mouse coordinates
+        // and the arguments of tools, so they are only allowed around the operators
+        // in the comma separated lists.
+        let (expr, spaced_expr) = match version {
+            0 => (Self::atom_parser().boxed(), Self::atom_parser().boxed()),
+            _ => (Self::expr_parser(false), Self::expr_parser(true)),
+        };
+
+        let coords = expr
+            .clone()
+            .then_ignore(padding_no_newline)
+            .then(expr.clone());
+
+        // Tick ranges and interpolations were added in version 1
+        let (tick_range_end, mouse) = match version {
+            0 => (
+                empty().to(None).boxed(),
+                coords.map(|(x, y)| Mouse::Move(x, y)).boxed(),
+            ),
+            _ => {
+                let curve = text::keyword("linear")
+                    .to(Curve::Linear)
+                    .or(text::keyword("ease").to(Curve::Ease));
+                let interpolation = curve
+                    .then(
+                        spaced_expr
+                            .clone()
+                            .padded()
+                            .then_ignore(just(','))
+                            .then(spaced_expr.clone().padded())
+                            .delimited_by(just('('), just(')')),
+                    )
+                    .map(|(curve, (x, y))| Mouse::Interpolate { curve, x, y });
+
+                (
+                    just("..")
+                        .ignore_then(tick)
+                        .map(|(relative, tick)| (relative.is_some(), tick))
+                        .or_not()
+                        .boxed(),
+                    interpolation
+                        .or(coords.map(|(x, y)| Mouse::Move(x, y)))
+                        .boxed(),
+                )
+            }
+        };
+
+        let mouse_move_part = padding_no_newline
+            .ignore_then(just('|'))
+            .ignore_then(padding_no_newline)
+            .ignore_then(mouse.or_not())
+            .or_not()
+            .map(|c| c.flatten());
+
+        let tool = padding_no_newline
+            .ignore_then(text::ident())
+            .then(padding_no_newline.ignore_then(expr).repeated())
+            .then_ignore(padding_no_newline)
+            .map_with_span(|(name, args), span| ToolCall { name, args, span })
+            // Only `setpos` existed in version 0
+            .try_map(move |call: ToolCall, span| match version {
+                0 if call.name != "setpos" => Err(Simple::custom(
+                    span,
+                    format!(
+                        "Tool `{}` was added in version 1, upgrade the script to use it",
+                        call.name
+                    ),
+                )),
+                _ => Ok(call),
+            });
+
+        let tools_list = padding_no_newline.ignore_then(tool.separated_by(just(";")));
+
+        let tools_part = padding_no_newline
+            .ignore_then(just('|'))
+            .ignore_then(padding_no_newline)
+            .ignore_then(tools_list.or_not())
+            .or_not()
+            .map(|c| c.flatten());
+
+        let line = tick
+            .then(tick_range_end)
+            .then_ignore(just('>'))
+            .then(keys)
+            .then(mouse_move_part)
+            .then(tools_part)
+            .map_with_span(
+                |(((((is_relative, tick), end), keys), mouse), tools), span| InputLine {
+                    relative: is_relative.is_some(),
+                    tick,
+                    end,
+                    keys,
+                    mouse,
+                    tools,
+                    span,
+                },
+            )
+            .try_map(|line, span| {
+                let interpolated = matches!(line.mouse, Some(Mouse::Interpolate { .. }));
+                match (line.end, interpolated) {
+                    (Some(_), false) => Err(Simple::custom(
+                        span,
+                        "Tick ranges need an interpolated mouse movement, like `ease(300, -40)`",
+                    )),
+                    (None, true) => Err(Simple::custom(
+                        span,
+                        "Interpolated mouse movements need a tick range, like `+1..+20`",
+                    )),
+                    _ => Ok(line),
+                }
+            });
+
+        let block_line = line.clone().try_map(|line, span| {
+            if line.relative && line.end.is_none_or(|(relative, _)| relative) {
+                Ok(line)
+            } else {
+                Err(Simple::custom(
+                    span,
+                    "Lines in a block must use relative ticks",
+                ))
+            }
+        });
+
+        let call = text::ident()
+            .then(
+                spaced_expr
+                    .clone()
+                    .padded()
+                    .separated_by(just(','))
+                    .delimited_by(just('('), just(')')),
+            )
+            .map_with_span(|(name, args), span| Statement::Call { name, args, span });
+
+        let wait = text::keyword("wait")
+            .ignore_then(padding_no_newline)
+            .ignore_then(
+                text::keyword("puzzle_unlock")
+                    .to(Wait::PuzzleUnlock)
+                    .or(text::keyword("interaction")
+                        .ignore_then(just("==").padded())
+                        .ignore_then(text::ident().try_map(|name: String, span| {
+                            Wait::interaction_from_name(&name)
+                                .map_err(|err| Simple::custom(span, err))
+                        }))),
+            )
+            .map_with_span(|wait, span| Statement::Wait { wait, span });
+
+        let label = just('@')
+            .then(text::keyword("label"))
+            .ignore_then(padding_no_newline)
+            .ignore_then(text::ident())
+            .map_with_span(|name, span| Statement::Label { name, span });
+
+        let let_statement = text::keyword("let")
+            .ignore_then(padding_no_newline)
+            .ignore_then(text::ident())
+            .then_ignore(just('=').padded_by(padding_no_newline))
+            .then(spaced_expr)
+            .map_with_span(|(name, value), span| Statement::Let { name, value, span });
+
+        // Statements allowed inside of blocks
+        let block_statement = recursive(|block_statement| {
+            let repeat = text::keyword("repeat")
+                .ignore_then(padding_no_newline)
+                .ignore_then(text::int(10).try_map(|s: String, span| match s.parse() {
+                    Ok(count) if count <= MAX_REPEAT => Ok(count),
+                    _ => Err(Simple::custom(
+                        span,
+                        format!("Cannot repeat a block {s} times, the most is {MAX_REPEAT}"),
+                    )),
+                }))
+                .then(Self::block_parser(block_statement))
+                .map_with_span(|(count, body), span| Statement::Repeat { count, body, span });
+
+            block_line
+                .map(Statement::Line)
+                .or(repeat)
+                .or(wait)
+                .or(label)
+                .or(let_statement)
+                .or(call)
+        });
+
+        let macro_def = text::keyword("macro")
+            .ignore_then(padding_no_newline)
+            .ignore_then(text::ident())
+            .then(
+                text::ident()
+                    .padded()
+                    .separated_by(just(','))
+                    .delimited_by(just('('), just(')')),
+            )
+            .then(Self::block_parser(block_statement.clone()))
+            .map_with_span(|((name, params), body), span| Statement::Macro {
+                name,
+                params,
+                body,
+                span,
+            });
+
+        let include = text::keyword("include")
+            .ignore_then(padding_no_newline)
+            .ignore_then(Self::quoted_parser())
+            .map_with_span(|path, span| Statement::Include {
+                path,
+                span,
+                file: None,
+            });
+
+        // Everything but the input lines was added in version 1:
+        // repeat blocks, macros, includes, waits, labels and variables
+        let statement = match version {
+            0 => line.map(Statement::Line).boxed(),
+            _ => line
+                .map(Statement::Line)
+                .or(macro_def)
+                .or(include)
+                .or(block_statement)
+                .boxed(),
+        };
+
+        Self::layout_parser(statement, 1).then_ignore(text::whitespace())
+    }
+
+    /// Parser for a number or a name
+    fn atom_parser() -> impl Parser<char, Expr, Error = Simple<char>> + Clone {
+        let number = just('-')
+            .or_not()
+            .chain::<char, _, _>(text::int(10))
+            .chain::<char, _, _>(
+                just('.')
+                    .chain::<char, _, _>(text::digits(10).or_not())
+                    .or_not()
+                    .map(|fract| fract.unwrap_or_default()),
+            )
+            .collect::<String>();
+
+        number.map(Expr::Literal).or(text::ident().map(Expr::Param))
+    }
+
+    /// Parser for an arithmetic expression. Spaces around the operators are
+    /// allowed between parentheses, and everywhere else if `spaced` is set.
+    fn expr_parser(spaced: bool) -> BoxedParser<'static, char, Expr, Simple<char>> {
+        let spaced_expr = recursive(|spaced_expr| Self::operations_parser(spaced_expr, true));
+        Self::operations_parser(spaced_expr, spaced).boxed()
+    }
+
+    /// Parser for the operations between atoms, with the usual precedence
+    fn operations_parser<'a>(
+        spaced_expr: impl Parser<char, Expr, Error = Simple<char>> + Clone + 'a,
+        spaced: bool,
+    ) -> impl Parser<char, Expr, Error = Simple<char>> + Clone + 'a {
+        let padding = filter(|c: &char| c.is_inline_whitespace()).repeated();
+        let op_padding = filter(move |c: &char| spaced && c.is_inline_whitespace()).repeated();
+        let op = |c, op| just(c).padded_by(op_padding).to(op);
+
+        let atom = Self::atom_parser().or(spaced_expr
+            .padded_by(padding)
+            .delimited_by(just('('), just(')'))
+            .map(|expr| Expr::Paren(Box::new(expr))));
+
+        let unary = recursive(|unary| {
+            atom.or(just('-')
+                .ignore_then(unary)
+                .map(|expr| Expr::Neg(Box::new(expr))))
+        });
+
+        let product = unary
+            .clone()
+            .then(
+                op('*', BinaryOp::Mul)
+                    .or(op('/', BinaryOp::Div))
+                    .then(unary)
+                    .repeated(),
+            )
+            .foldl(|lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)));
+
+        product
+            .clone()
+            .then(
+                op('+', BinaryOp::Add)
+                    .or(op('-', BinaryOp::Sub))
+                    .then(product)
+                    .repeated(),
+            )
+            .foldl(|lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    /// Parser for a comment, without the `//`
+    fn comment_parser() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+        just("//")
+            .ignore_then(filter(|c: &char| *c != '\r' && *c != '\n').repeated())
+            .collect()
+    }
+
+    /// Parser for a list of at least `min` statements,
+    /// along with the comments and blank lines between them
+    fn layout_parser(
+        statement: impl Parser<char, Statement, Error = Simple<char>> + Clone,
+        min: usize,
+    ) -> impl Parser<char, Vec<Statement>, Error = Simple<char>> + Clone {
+        // The number of newlines before a statement tells if it is
+        // on the same line as the previous one, or after a blank line
+        let spacing = filter(|c: &char| c.is_whitespace())
+            .repeated()
+            .map(|spacing| spacing.iter().filter(|&&c| c == '\n').count());
+
+        let comment = Self::comment_parser().map(|text| Statement::Comment {
+            text,
+            inline: false,
+        });
+
+        spacing
+            .then(comment.or(statement))
+            .map(|(newlines, mut statement)| {
+                if let Statement::Comment { inline, .. } = &mut statement {
+                    *inline = newlines == 0;
+                }
+
+                if newlines >= 2 {
+                    vec![Statement::Blank, statement]
+                } else {
+                    vec![statement]
+                }
+            })
+            .repeated()
+            .at_least(min)
+            .flatten()
+    }
+
+    /// Parser for the body of a block, between braces
+    fn block_parser(
+        statement: impl Parser<char, Statement, Error = Simple<char>> + Clone,
+    ) -> impl Parser<char, Vec<Statement>, Error = Simple<char>> + Clone {
+        Self::layout_parser(statement, 0)
+            .then_ignore(text::whitespace())
+            .delimited_by(just('{').padded(), just('}'))
+    }
+
+    /// Loads the files included by the statements, relative to `dir`.
+    /// `stack` holds the files currently being loaded, to detect cycles.
+    fn load_includes(
+        statements: &mut [Statement],
+        version: u64,
+        src: &str,
+        file: Option<&Path>,
+        dir: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), Vec<ScriptError>> {
+        for statement in statements {
+            let Statement::Include {
+                path,
+                span,
+                file: included,
+            } = statement
+            else {
+                continue;
+            };
+
+            let error = |message: String| vec![ScriptError::new(message, span.clone(), src, file)];
+            let context = format!("in file `{path}` included at {}", location(src, file, span));
+
+            let full_path = dir.join(&*path);
+            let canonical_path = full_path
+                .canonicalize()
+                .map_err(|err| error(format!("Cannot open `{path}`: {err}")))?;
+
+            if stack.contains(&canonical_path) {
+                return Err(vec![ScriptError::new(
+                    "Include cycle detected",
+                    span.clone(),
+                    src,
+                    file,
+                )
+                .with_hint(
+                    "A file cannot include itself, directly or through other files",
+                )]);
+            }
+
+            let include_src = std::fs::read_to_string(&full_path)
+                .map_err(|err| error(format!("Cannot read `{path}`: {err}")))?;
+
+            let mut statements = Self::statements_parser(version)
+                .then_ignore(end())
+                .parse(include_src.clone())
+                .map_err(|parse_errs| {
+                    parse_errs
+                        .iter()
+                        .map(|e| {
+                            ScriptError::from_parse_error(e, &include_src, Some(&full_path))
+                                .with_context(context.clone())
+                        })
+                        .collect::<Vec<_>>()
+                })?;
+
+            stack.push(canonical_path);
+            Self::load_includes(
+                &mut statements,
+                version,
+                &include_src,
+                Some(&full_path),
+                full_path.parent().unwrap_or(dir),
+                stack,
+            )
+            .map_err(|errs| {
+                errs.into_iter()
+                    .map(|err| err.with_context(context.clone()))
+                    .collect::<Vec<_>>()
+            })?;
+            stack.pop();
+
+            *included = Some(Box::new(IncludedFile {
+                path: full_path,
+                src: include_src,
+                statements,
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Performs additionnal checks on the script, and expands the statements into lines.
+    fn pre_process(&mut self, src: &str, path: Option<&Path>) -> Result<(), Box<ScriptError>> {
+        let mut expander = Expander::new(src, path, &self.statements)?;
+        expander.expand(&self.statements, &mut HashMap::new())?;
+        (self.lines, self.labels) = expander.finish()?;
+
+        Ok(())
+    }
+
+    /// Parses the source of the script file at `path`, which may not be saved yet.
+    /// Included files are still read from the disk.
+    pub fn from_source(src: String, path: &Path) -> Result<Self, Vec<ScriptError>> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse(src, dir, Some(path))
+    }
+
+    /// Parses the script. Included files are looked up relative to the current directory.
+    pub fn try_from(src: String) -> Result<Self, Vec<ScriptError>> {
+        Self::parse(src, Path::new("."), None)
+    }
+
+    /// Reads and parses the script file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, Vec<ScriptError>> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|err| vec![ScriptError::new(err.to_string(), 0..0, "", Some(path))])?;
+
+        Self::from_source(src, path)
+    }
+
+    fn parse(src: String, dir: &Path, path: Option<&Path>) -> Result<Self, Vec<ScriptError>> {
+        let mut script = Self::get_parser()
+            .parse(src.clone())
+            .map_err(|parse_errs| {
+                parse_errs
+                    .iter()
+                    .map(|e| ScriptError::from_parse_error(e, &src, path))
+                    .collect::<Vec<_>>()
+            })?;
+
+        let mut stack = path
+            .and_then(|path| path.canonicalize().ok())
+            .into_iter()
+            .collect();
+        // Included files use the version of the script
+        Self::load_includes(
+            &mut script.statements,
+            script.version,
+            &src,
+            path,
+            dir,
+            &mut stack,
+        )?;
+
+        script.pre_process(&src, path).map_err(|err| vec![*err])?;
+        script.src = src;
+        script.path = path.map(Path::to_path_buf);
+
+        if script.metadata.legit {
+            let errors = script.legit_errors();
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+        }
+
+        Ok(script)
+    }
+}
+
+/// Describes where `span` is, to give the context of an error
+fn location(src: &str, file: Option<&Path>, span: &Range<usize>) -> String {
+    let (line, _) = error::position(src, span.start);
+    match file {
+        Some(file) => format!("{}:{line}", file.display()),
+        None => format!("line {line}"),
+    }
+}
+
+/// A macro definition, along with the file it was defined in
+#[derive(Clone, Copy)]
+struct MacroDef<'a> {
+    params: &'a [String],
+    body: &'a [Statement],
+    src: &'a str,
+    file: Option<&'a Path>,
+}
+
+/// Expands the statements of a script into lines
+struct Expander<'a> {
+    /// Source of the file being expanded, to locate errors
+    src: &'a str,
+    file: Option<&'a Path>,
+    macros: HashMap<&'a str, MacroDef<'a>>,
+    /// Names of the macros currently being expanded
+    call_stack: Vec<&'a str>,
+    lines: Vec<ScriptLine>,
+    /// The line stopping the mouse after an interpolation, added before the next line
+    mouse_reset: Option<ScriptLine>,
+    /// The waits and the labels for the next line
+    waits: Vec<Wait>,
+    next_labels: Vec<String>,
+    /// The error to report if no line follows the waits and labels
+    unfollowed: Option<ScriptError>,
+    labels: Vec<Label>,
+    /// The tick each held key is released on
+    holds: HashMap<Input, u32>,
+}
+
+impl<'a> Expander<'a> {
+    /// Creates the expander, collecting the macros defined in the statements
+    fn new(
+        src: &'a str,
+        file: Option<&'a Path>,
+        statements: &'a [Statement],
+    ) -> Result<Self, Box<ScriptError>> {
+        let mut expander = Self {
+            src,
+            file,
+            macros: HashMap::new(),
+            call_stack: vec![],
+            lines: vec![],
+            mouse_reset: None,
+            waits: vec![],
+            next_labels: vec![],
+            unfollowed: None,
+            labels: vec![],
+            holds: HashMap::new(),
+        };
+
+        expander.collect_macros(src, file, statements, &mut HashSet::new())?;
+
+        Ok(expander)
+    }
+
+    /// Registers the macros defined in the statements, and in the included files.
+    /// `collected` holds the included files already seen, as several segments can
+    /// include the same file.
+    fn collect_macros(
+        &mut self,
+        src: &'a str,
+        file: Option<&'a Path>,
+        statements: &'a [Statement],
+        collected: &mut HashSet<PathBuf>,
+    ) -> Result<(), Box<ScriptError>> {
+        for statement in statements {
+            match statement {
+                Statement::Macro {
+                    name,
+                    params,
+                    body,
+                    span,
+                } => {
+                    let def = MacroDef {
+                        params,
+                        body,
+                        src,
+                        file,
+                    };
+
+                    if self.macros.insert(name, def).is_some() {
+                        return Err(ScriptError::new(
+                            format!("Macro `{name}` is already defined"),
+                            span.clone(),
+                            src,
+                            file,
+                        )
+                        .into());
+                    }
+                }
+                Statement::Include {
+                    file: Some(included),
+                    ..
+                } => {
+                    let path = included
+                        .path
+                        .canonicalize()
+                        .unwrap_or_else(|_| included.path.clone());
+                    if collected.insert(path) {
+                        self.collect_macros(
+                            &included.src,
+                            Some(&included.path),
+                            &included.statements,
+                            collected,
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn error(&self, message: impl Into<String>, span: &Range<usize>) -> ScriptError {
+        ScriptError::new(message, span.clone(), self.src, self.file)
+    }
+
+    /// Adds a line, stopping the mouse first if an interpolation ended before it
+    fn push_line(&mut self, mut line: ScriptLine) {
+        if let Some(reset) = self.mouse_reset.take() {
+            if reset.absolute_tick < line.absolute_tick {
+                self.lines.push(reset);
+            } else if line.mouse.is_none() {
+                line.mouse = reset.mouse;
+            }
+        }
+
+        self.lines.push(line);
+    }
+
+    /// Returns the expanded lines and the labels
+    fn finish(mut self) -> Result<(Vec<ScriptLine>, Vec<Label>), Box<ScriptError>> {
+        if let Some(err) = self.unfollowed {
+            return Err(err.into());
+        }
+
+        self.lines.extend(self.mouse_reset.take());
+
+        Ok((self.lines, self.labels))
+    }
+
+    /// Expands the statements with `src` and `file` as the current file
+    fn expand_in(
+        &mut self,
+        src: &'a str,
+        file: Option<&'a Path>,
+        statements: &'a [Statement],
+        env: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<ScriptError>> {
+        let previous_src = std::mem::replace(&mut self.src, src);
+        let previous_file = std::mem::replace(&mut self.file, file);
+        let res = self.expand(statements, env);
+        self.src = previous_src;
+        self.file = previous_file;
+
+        res
+    }
+
+    /// Appends the lines of the statements, and resolves their ticks.
+    /// `env` holds the values of the variables and of the parameters of the current macro.
+    fn expand(
+        &mut self,
+        statements: &'a [Statement],
+        env: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<ScriptError>> {
+        // Comments on their own line are attached to the line that follows them
+        let mut comments = vec![];
+        let mut previous: Option<&Statement> = None;
+        // Index of the first line of the last input line, for its inline comment
+        let mut written = None;
+
+        for statement in statements {
+            match statement {
+                Statement::Line(line) => {
+                    let mut tick = line.tick;
+
+                    if !self.waits.is_empty() && !line.relative {
+                        return Err(self
+                            .error("Lines after a wait must use relative ticks", &line.span)
+                            .with_hint("Their tick is counted from when the wait ends")
+                            .into());
+                    }
+
+                    // The mouse must not keep moving during a wait. It stops on its
+                    // own tick, and the line after the wait counts from there.
+                    if !self.waits.is_empty() {
+                        self.lines.extend(self.mouse_reset.take());
+                    }
+
+                    // Set relative ticks to absolute and check
+                    // that they are increasing
+                    if let Some(previous) = self.lines.last() {
+                        if line.relative {
+                            tick = previous.absolute_tick.checked_add(tick).ok_or_else(|| {
+                                self.error(
+                                    format!(
+                                        "Tick +{tick} after tick {} is too large",
+                                        previous.absolute_tick
+                                    ),
+                                    &line.span,
+                                )
+                            })?;
+                        }
+
+                        if previous.absolute_tick >= tick {
+                            let hint = if line.relative {
+                                "Relative ticks must be at least +1".to_string()
+                            } else {
+                                format!(
+                                    "Use a tick of at least {}, or a relative tick like +1",
+                                    previous.absolute_tick + 1
+                                )
+                            };
+
+                            return Err(self
+                                .error(
+                                    format!(
+                                        "Expected tick bigger than {}, got {tick}",
+                                        previous.absolute_tick
+                                    ),
+                                    &line.span,
+                                )
+                                .with_hint(hint)
+                                .into());
+                        }
+                    }
+
+                    let end = match line.end {
+                        Some((relative, end)) => {
+                            let end = match self.lines.last() {
+                                Some(previous) if relative => {
+                                    previous.absolute_tick.checked_add(end).ok_or_else(|| {
+                                        self.error(
+                                            "The tick range ends past the largest tick",
+                                            &line.span,
+                                        )
+                                    })?
+                                }
+                                _ => end,
+                            };
+
+                            if end < tick {
+                                return Err(self
+                                    .error(
+                                        format!(
+                                            "The tick range ends on tick {end}, before its start on tick {tick}"
+                                        ),
+                                        &line.span,
+                                    )
+                                    .into());
+                            }
+
+                            // Each tick of the range is a line, and the mouse stops after it
+                            if self.lines.len() + (end - tick) as usize >= MAX_LINES {
+                                return Err(self
+                                    .error(
+                                        format!(
+                                            "The script expands into more than {MAX_LINES} lines"
+                                        ),
+                                        &line.span,
+                                    )
+                                    .with_hint("Use a shorter tick range")
+                                    .into());
+                            }
+                            if end == u32::MAX {
+                                return Err(self
+                                    .error(
+                                        "The tick range ends on the largest tick, the mouse cannot stop after it",
+                                        &line.span,
+                                    )
+                                    .into());
+                            }
+                            Some(end)
+                        }
+                        None => None,
+                    };
+
+                    for press in &line.keys {
+                        let Some(hold) = press.hold else { continue };
+                        let input = press.key.action.input();
+
+                        if let Some(release) =
+                            self.holds.get(&input).filter(|release| **release > tick)
+                        {
+                            return Err(self
+                                .error(
+                                    format!("`{}` is still held by a previous line until tick {release}", press.key),
+                                    &line.span,
+                                )
+                                .with_hint("Holds of the same key cannot overlap")
+                                .into());
+                        }
+                        let release = tick.checked_add(hold).ok_or_else(|| {
+                            self.error(
+                                format!(
+                                    "`{}` is held for {hold} ticks, past the largest tick",
+                                    press.key
+                                ),
+                                &line.span,
+                            )
+                        })?;
+                        self.holds.insert(input, release);
+                    }
+
+                    let eval_i32 = |expr: &Expr| {
+                        expr.eval_i32(env)
+                            .map_err(|err| Box::new(self.error(err, &line.span)))
+                    };
+                    let (mouse, interpolation) = match &line.mouse {
+                        Some(Mouse::Move(x, y)) => (Some((eval_i32(x)?, eval_i32(y)?)), None),
+                        Some(Mouse::Interpolate { curve, x, y }) => {
+                            (None, Some((curve, eval_i32(x)?, eval_i32(y)?)))
+                        }
+                        None => (None, None),
+                    };
+
+                    let tools = match &line.tools {
+                        Some(tools) => Some(
+                            tools
+                                .iter()
+                                .map(|tool| {
+                                    Tool::from_call(tool, env)
+                                        .map_err(|err| Box::new(self.error(err, &tool.span)))
+                                })
+                                .collect::<Result<_, _>>()?,
+                        ),
+                        None => None,
+                    };
+
+                    let script_line = ScriptLine {
+                        relative: line.relative,
+                        tick: line.tick,
+                        absolute_tick: tick,
+                        keys: line.keys.clone(),
+                        mouse,
+                        tools,
+                        span: line.span.clone(),
+                        file: self.file.map(Path::to_path_buf),
+                        generated: false,
+                        comments: std::mem::take(&mut comments),
+                        inline_comment: None,
+                        waits: std::mem::take(&mut self.waits),
+                    };
+                    self.unfollowed = None;
+
+                    match (end, interpolation) {
+                        // Interpolated lines play one movement per tick of their range,
+                        // the keys and tools are only on the first one
+                        (Some(end), Some((curve, x, y))) => {
+                            let ticks = end - tick + 1;
+                            let movements = curve.spread(x, ticks).into_iter();
+                            let movements = movements.zip(curve.spread(y, ticks));
+
+                            for (i, movement) in movements.enumerate() {
+                                let mouse = Some(movement);
+                                if i == 0 {
+                                    self.push_line(ScriptLine {
+                                        mouse,
+                                        ..script_line.clone()
+                                    });
+                                    written = Some(self.lines.len() - 1);
+                                } else {
+                                    self.push_line(ScriptLine {
+                                        absolute_tick: tick + i as u32,
+                                        keys: vec![],
+                                        mouse,
+                                        tools: None,
+                                        comments: vec![],
+                                        waits: vec![],
+                                        generated: true,
+                                        ..script_line.clone()
+                                    });
+                                }
+                            }
+
+                            // The mouse stops after the range
+                            self.mouse_reset = Some(ScriptLine {
+                                absolute_tick: end + 1,
+                                keys: vec![],
+                                mouse: Some((0, 0)),
+                                tools: None,
+                                comments: vec![],
+                                waits: vec![],
+                                generated: true,
+                                ..script_line
+                            });
+                        }
+                        _ => {
+                            self.push_line(script_line);
+                            written = Some(self.lines.len() - 1);
+                        }
+                    }
+
+                    for name in self.next_labels.drain(..) {
+                        self.labels.push(Label {
+                            name,
+                            line: written.unwrap(),
+                            tick,
+                        });
+                    }
+                }
+                Statement::Repeat { count, body, span } => {
+                    let location = location(self.src, self.file, span);
+                    for iteration in 1..=*count {
+                        // Variables defined in the block only last for one iteration
+                        self.expand(body, &mut env.clone()).map_err(|err| {
+                            err.with_context(format!(
+                                "in repeat block at {location}, iteration {iteration}"
+                            ))
+                        })?;
+
+                        if self.lines.len() > MAX_LINES {
+                            return Err(self
+                                .error(
+                                    format!("The script expands into more than {MAX_LINES} lines"),
+                                    span,
+                                )
+                                .into());
+                        }
+                    }
+                }
+                // Macros are only expanded when called
+                Statement::Macro { .. } => {}
+                Statement::Comment {
+                    text,
+                    inline: false,
+                } => comments.push(text.clone()),
+                // Inline comments after a line belong to it
+                Statement::Comment { text, inline: true } => {
+                    if let (Some(Statement::Line(_)), Some(index)) = (previous, written) {
+                        self.lines[index].inline_comment = Some(text.clone());
+                    }
+                }
+                Statement::Blank => {}
+                Statement::Let { name, value, span } => {
+                    let value = value.eval(env).map_err(|err| self.error(err, span))?;
+                    env.insert(name.clone(), value);
+                }
+                Statement::Wait { wait, span } => {
+                    self.waits.push(*wait);
+                    self.unfollowed = Some(self.error("Waits must be followed by a line", span));
+                }
+                Statement::Label { name, span } => {
+                    let labels = self.labels.iter().map(|label| &label.name);
+                    if labels.chain(&self.next_labels).any(|label| label == name) {
+                        return Err(self
+                            .error(format!("Label `{name}` is already defined"), span)
+                            .with_hint(
+                                "Labels in repeat blocks and macros are defined each time they run",
+                            )
+                            .into());
+                    }
+
+                    self.next_labels.push(name.clone());
+                    self.unfollowed = Some(self.error("Labels must be followed by a line", span));
+                }
+                Statement::Call { name, args, span } => {
+                    let Some(&def) = self.macros.get(name.as_str()) else {
+                        return Err(self
+                            .error(format!("Unknown macro `{name}`"), span)
+                            .with_hint("Macros are defined with `macro <name>(<params>) { ... }`")
+                            .into());
+                    };
+
+                    if def.params.len() != args.len() {
+                        return Err(self
+                            .error(
+                                format!(
+                                    "Macro `{name}` expects {} arguments, got {}",
+                                    def.params.len(),
+                                    args.len()
+                                ),
+                                span,
+                            )
+                            .into());
+                    }
+
+                    if self.call_stack.contains(&name.as_str()) {
+                        return Err(self.error("Macros cannot call themselves", span).into());
+                    }
+
+                    // Macros see the variables of the caller
+                    let mut macro_env = env.clone();
+                    for (param, arg) in def.params.iter().zip(args) {
+                        let value = arg.eval(env).map_err(|err| self.error(err, span))?;
+                        macro_env.insert(param.clone(), value);
+                    }
+
+                    let context = format!(
+                        "in macro `{name}` called at {}",
+                        location(self.src, self.file, span)
+                    );
+
+                    self.call_stack.push(name);
+                    let res = self.expand_in(def.src, def.file, def.body, &mut macro_env);
+                    self.call_stack.pop();
+                    res.map_err(|err| err.with_context(context))?;
+                }
+                Statement::Include { path, span, file } => {
+                    let Some(file) = file else {
+                        return Err(self
+                            .error(format!("File `{path}` was not loaded"), span)
+                            .into());
+                    };
+
+                    let context = format!(
+                        "in file `{path}` included at {}",
+                        location(self.src, self.file, span)
+                    );
+
+                    self.expand_in(&file.src, Some(&file.path), &file.statements, env)
+                        .map_err(|err| err.with_context(context))?;
+                }
+            }
+
+            if !matches!(statement, Statement::Comment { inline: false, .. }) {
+                comments.clear();
+            }
+            previous = Some(statement);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{Assertion, Label, PrintOptions, Script, TickStyle, Tool, Wait};
+    use crate::witness::witness_types::{InteractionStatus, Vec2, Vec3};
+    use chumsky::Parser;
+
+    #[test]
+    fn test_parser() {
+        let script = "
+        version 0
+        start now
+        
+        1>|0 0
+        2>|
+        3>|0 0 
+        4>|0 0 // test
+        5>|0 0//test
+        ";
+
+        let res = Script::get_parser().parse(script);
+        assert!(res.is_ok())
+    }
+
+    #[test]
+    fn test_parser_latest() {
+        let script = "
+        version 1
+        start now
+        
+        1>|0 0
+        2>|
+        3>|0 0 
+        4>|0 0 // test
+        5>|0 0//test
+        ";
+
+        let res = Script::get_parser().parse(script);
+        assert!(res.is_ok())
+    }
+
+    #[test]
+    fn test_repeat() {
+        let script = "
+        version 1
+        start now
+
+        10>P
+        repeat 3 {
+            +2>|5 0 // wiggle
+            +1>|0 0
+            repeat 2 {
+                +1>P
+            }
+        }
+        +5>p
+        ";
+
+        let script = Script::try_from(script.to_string()).unwrap();
+        let ticks: Vec<u32> = script.lines.iter().map(|line| line.absolute_tick).collect();
+        assert_eq!(
+            ticks,
+            vec![10, 12, 13, 14, 15, 17, 18, 19, 20, 22, 23, 24, 25, 30]
+        );
+
+        // The ticks are kept as written
+        assert!(!script.lines[0].relative);
+        assert!(script.lines[1..].iter().all(|line| line.relative));
+        assert_eq!(script.lines[1].tick, 2);
+        assert_eq!(script.lines[1].inline_comment.as_deref(), Some(" wiggle"));
+    }
+
+    #[test]
+    fn test_lines() {
+        let src = "version 0
+start now
+
+// first
+// second
+1>P // inline
++1>p|10 0
+
+// detached
+
++1>U
+";
+
+        let script = Script::try_from(src.to_string()).unwrap();
+        let lines = &script.lines;
+        assert_eq!(lines[0].comments, vec![" first", " second"]);
+        assert_eq!(lines[0].inline_comment.as_deref(), Some(" inline"));
+        assert_eq!(&src[lines[1].span.clone()], "+1>p|10 0");
+        assert_eq!((lines[1].relative, lines[1].tick), (true, 1));
+        assert_eq!(lines[1].absolute_tick, 2);
+        assert!(lines[1].comments.is_empty() && lines[1].inline_comment.is_none());
+        assert!(lines[2].comments.is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let errors = Script::try_from("version 3\nstart now\n1>\n".to_string()).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Unsupported version 3, the latest version is 1"
+        );
+        assert_eq!((errors[0].line, errors[0].column), (1, 9));
+        assert_eq!(errors[0].span, 8..9);
+
+        let errors = Script::try_from("version 0\nstart now\n1>\n2>U|?\n".to_string()).unwrap_err();
+        assert_eq!((errors[0].line, errors[0].column), (4, 5));
+        assert_eq!(errors[0].found.as_deref(), Some("'?'"));
+        assert!(errors[0].expected.contains(&"'-'".to_string()));
+
+        let errors =
+            Script::try_from("version 0\nstart now\n10>\n  5>U\n".to_string()).unwrap_err();
+        assert_eq!(errors[0].message, "Expected tick bigger than 10, got 5");
+        assert_eq!((errors[0].line, errors[0].column), (4, 3));
+        assert!(errors[0].hint.is_some());
+    }
+
+    #[test]
+    fn test_repeat_errors() {
+        let script = "
+        version 1
+        start now
+
+        1>P
+        repeat 2 {
+            5>p
+        }
+        ";
+        let errors = Script::try_from(script.to_string()).unwrap_err();
+        assert_eq!(errors[0].line, 7, "{errors:?}");
+
+        let script = "
+        version 1
+        start now
+
+        1>P
+        repeat 2 {
+            +1>p
+            repeat 3 {
+                +0>P
+            }
+        }
+        ";
+        let errors = Script::try_from(script.to_string()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 9:17: Expected tick bigger than 2, got 2 \
+             (in repeat block at line 8, iteration 1) \
+             (in repeat block at line 6, iteration 1)"
+        );
+
+        // Huge counts are refused, even nested ones
+        let errors = Script::try_from(
+            "version 1\nstart now\nrepeat 99999999999 {\n    +1>P\n}\n".to_string(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Cannot repeat a block 99999999999 times, the most is 10000"
+        );
+        let errors = Script::try_from(
+            "version 1\nstart now\nrepeat 10000 {\n    repeat 10000 {\n        +1>P\n    }\n}\n"
+                .to_string(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 4:5: The script expands into more than 1000000 lines \
+             (in repeat block at line 3, iteration 101)"
+        );
+
+        let errors =
+            Script::try_from("version 1\nstart now\n99999999999>P\n".to_string()).unwrap_err();
+        assert_eq!(errors[0].message, "Tick 99999999999 is too large");
+
+        // Relative ticks too, when they are added up
+        let errors = Script::try_from(
+            "version 1\nstart now\n4000000000>P\nrepeat 2 {\n    +200000000>p\n}\n".to_string(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 5:5: Tick +200000000 after tick 4200000000 is too large \
+             (in repeat block at line 4, iteration 2)"
+        );
+    }
+
+    #[test]
+    fn test_macro() {
+        let script = "
+        version 1
+        start now
+
+        macro solve_2x2(dx, dy) {
+            +1>P
+            +1>|dx dy
+            +1>P|0 0
+        }
+
+        macro tp(x) {
+            +1>||setpos x 2.5 3 0 -0.5
+        }
+
+        10>
+        solve_2x2(-15, 7)
+        repeat 2 {
+            solve_2x2(1, 2)
+        }
+        tp(1.5)
+        ";
+
+        let script = Script::try_from(script.to_string()).unwrap();
+        let ticks: Vec<u32> = script.lines.iter().map(|line| line.absolute_tick).collect();
+        assert_eq!(ticks, (10..=20).collect::<Vec<_>>());
+
+        let mouse: Vec<_> = script.lines.iter().map(|line| line.mouse).collect();
+        assert_eq!(mouse[2], Some((-15, 7)));
+        assert_eq!(mouse[8], Some((1, 2)));
+
+        let Some(Tool::SetPos { pos, ang }) = script.lines[10].tools.as_ref().map(|t| &t[0]) else {
+            panic!("Expected setpos tool")
+        };
+        assert_eq!(
+            pos,
+            &Vec3 {
+                x: 1.5,
+                y: 2.5,
+                z: 3.0
+            }
+        );
+        assert_eq!(ang, &Vec2 { x: 0.0, y: -0.5 });
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let script = "version 1
+start now
+10>U|5 0
++1..+4>P|linear(10, -40) // turn
++1>
+20..21>|ease(3, 0)
++1>|1 1
+";
+        let script = Script::try_from(script.to_string()).unwrap();
+        let lines: Vec<_> = script
+            .lines
+            .iter()
+            .map(|line| {
+                let keys: Vec<_> = line.keys.iter().map(|press| press.key.token).collect();
+                (line.absolute_tick, keys, line.mouse)
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (10, vec!["U"], Some((5, 0))),
+                (11, vec!["P"], Some((3, -10))),
+                (12, vec![], Some((2, -10))),
+                (13, vec![], Some((3, -10))),
+                (14, vec![], Some((2, -10))),
+                // The mouse stops on the next line
+                (15, vec![], Some((0, 0))),
+                (20, vec![], Some((2, 0))),
+                (21, vec![], Some((1, 0))),
+                // Unless it moves again
+                (22, vec![], Some((1, 1))),
+            ]
+        );
+        assert_eq!(script.lines[1].inline_comment.as_deref(), Some(" turn"));
+
+        // The mouse is stopped after the range when no line follows
+        let script =
+            Script::try_from("version 1\nstart now\n1..3>|linear(3, 0)\n".to_string()).unwrap();
+        let last = script.lines.last().unwrap();
+        assert_eq!((last.absolute_tick, last.mouse), (4, Some((0, 0))));
+
+        let errors = |script: &str| {
+            let errors = Script::try_from(format!("version 1\nstart now\n{script}")).unwrap_err();
+            errors[0].message.clone()
+        };
+        assert_eq!(
+            errors("1..3>|10 0\n"),
+            "Tick ranges need an interpolated mouse movement, like `ease(300, -40)`"
+        );
+        assert_eq!(
+            errors("1>|ease(10, 0)\n"),
+            "Interpolated mouse movements need a tick range, like `+1..+20`"
+        );
+        assert_eq!(
+            errors("5..3>|ease(10, 0)\n"),
+            "The tick range ends on tick 3, before its start on tick 5"
+        );
+        assert_eq!(
+            errors("+1..+4000000000>|ease(1, 1)\n"),
+            "The script expands into more than 1000000 lines"
+        );
+        assert_eq!(
+            errors("4000000000>\n+1..+400000000>|ease(1, 1)\n"),
+            "The tick range ends past the largest tick"
+        );
+        assert_eq!(
+            errors("4294967290..4294967295>|ease(1, 1)\n"),
+            "The tick range ends on the largest tick, the mouse cannot stop after it"
+        );
+        assert_eq!(
+            errors("1>\nrepeat 2 {\n+1..5>|ease(10, 0)\n}\n"),
+            "Lines in a block must use relative ticks"
+        );
+
+        // Ranges only exist since version 1
+        assert!(Script::try_from("version 0\nstart now\n1..3>|ease(3, 0)\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_wait() {
+        let src = "version 1
+start now
+10>P
+wait puzzle_unlock
++5>p
++1..+2>|linear(4, 0)
+wait interaction == Walking
+wait puzzle_unlock
++1>U
+";
+        let script = Script::try_from(src.to_string()).unwrap();
+        let lines: Vec<_> = script
+            .lines
+            .iter()
+            .map(|line| (line.absolute_tick, line.mouse, line.waits.clone()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (10, None, vec![]),
+                (15, None, vec![Wait::PuzzleUnlock]),
+                (16, Some((2, 0)), vec![]),
+                (17, Some((2, 0)), vec![]),
+                // The mouse stops before waiting, on its own tick
+                (18, Some((0, 0)), vec![]),
+                (
+                    19,
+                    None,
+                    vec![
+                        Wait::Interaction(InteractionStatus::Walking),
+                        Wait::PuzzleUnlock
+                    ]
+                ),
+            ]
+        );
+
+        // Lines after a wait stay relative
+        let options = PrintOptions {
+            ticks: TickStyle::Absolute,
+            ..Default::default()
+        };
+        assert!(script
+            .print(&options)
+            .ends_with("wait interaction == Walking\nwait puzzle_unlock\n+1>U\n"));
+        assert_eq!(script.to_string(), src);
+
+        let error = |script: &str| {
+            let errors = Script::try_from(format!("version 1\nstart now\n{script}")).unwrap_err();
+            errors[0].to_string()
+        };
+        assert_eq!(
+            error("1>\nwait puzzle_unlock\n5>\n"),
+            "line 5:1: Lines after a wait must use relative ticks"
+        );
+        assert_eq!(
+            error("1>\nwait puzzle_unlock\n"),
+            "line 4:1: Waits must be followed by a line"
+        );
+        assert_eq!(
+            error("1>\nwait interaction == Running\n+1>\n"),
+            "line 4:21: Unknown interaction status `Running`, expected one of: FocusMode, SolvingPanel, Walking, Cinematic"
+        );
+
+        // Waits only exist since version 1
+        assert!(Script::try_from(
+            "version 0\nstart now\n1>\nwait puzzle_unlock\n+1>\n".to_string()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_label() {
+        let src = "version 1
+start now
+10>P
+@label bunker
++5>p
+repeat 2 {
+    +1>U
+}
+wait puzzle_unlock
+@label after_unlock
+@label door
++3>u
+";
+        let script = Script::try_from(src.to_string()).unwrap();
+        assert_eq!(
+            script.labels,
+            vec![
+                Label {
+                    name: "bunker".to_string(),
+                    line: 1,
+                    tick: 15
+                },
+                Label {
+                    name: "after_unlock".to_string(),
+                    line: 4,
+                    tick: 20
+                },
+                Label {
+                    name: "door".to_string(),
+                    line: 4,
+                    tick: 20
+                },
+            ]
+        );
+        assert_eq!(script.to_string(), src);
+
+        let error = |script: &str| {
+            let errors = Script::try_from(format!("version 1\nstart now\n{script}")).unwrap_err();
+            errors[0].to_string()
+        };
+        assert_eq!(
+            error("1>\n@label a\n+1>\n@label a\n+1>\n"),
+            "line 6:1: Label `a` is already defined"
+        );
+        assert_eq!(
+            error("repeat 2 {\n    @label a\n    +1>\n}\n"),
+            "line 4:5: Label `a` is already defined (in repeat block at line 3, iteration 2)"
+        );
+        assert_eq!(
+            error("1>\n@label end\n"),
+            "line 4:1: Labels must be followed by a line"
+        );
+
+        // Labels only exist since version 1
+        assert!(Script::try_from("version 0\nstart now\n@label a\n1>\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        let src = "version 1
+start now
+let turn = 300
+let half = turn/2
+let fast = (turn + half) * 2
+macro look(dx) {
+    +1>|dx*2 -dx
+}
+1>|half -(half-50)
++1..+2>|linear(fast - 100, turn / -3)
+repeat 2 {
+    let turn = turn+1
+    +1>|turn 0
+}
++1>|turn 0|setpos turn/100 -1.5*2 0 0 0
+look(half + 1)
+";
+        let script = Script::try_from(src.to_string()).unwrap();
+        let mouse: Vec<_> = script.lines.iter().map(|line| line.mouse).collect();
+        assert_eq!(
+            mouse,
+            vec![
+                Some((150, -100)),
+                Some((400, -50)),
+                Some((400, -50)),
+                // Variables defined in a block are only visible in it
+                Some((301, 0)),
+                Some((301, 0)),
+                Some((300, 0)),
+                Some((302, -151)),
+            ]
+        );
+        let Some(Tool::SetPos { pos, .. }) = script.lines[5].tools.as_ref().map(|t| &t[0]) else {
+            panic!("Expected setpos tool")
+        };
+        assert_eq!(
+            pos,
+            &Vec3 {
+                x: 3.,
+                y: -3.,
+                z: 0.
+            }
+        );
+
+        // Expressions are printed without spaces, which is valid in every field
+        let printed = script.to_string();
+        assert!(printed.contains("let fast = (turn+half)*2\n"));
+        assert!(printed.contains("+1..+2>|linear(fast-100, turn/-3)\n"));
+        assert_eq!(
+            Script::try_from(printed.clone()).unwrap().to_string(),
+            printed
+        );
+
+        let error = |script: &str| {
+            let errors = Script::try_from(format!("version 1\nstart now\n{script}")).unwrap_err();
+            errors[0].to_string()
+        };
+        assert_eq!(error("let a = 1/0\n1>\n"), "line 3:1: Division by zero");
+        assert_eq!(
+            error("repeat 1 {\n    let a = 1\n    +1>\n}\n+1>|a 0\n"),
+            "line 7:1: Unknown variable or parameter `a`"
+        );
+        assert_eq!(
+            error("let a = 1/3\n1>|a 0\n"),
+            "line 4:1: Expected integer, got 0.3333333333333333"
+        );
+
+        // Variables and arithmetic only exist since version 1
+        assert!(Script::try_from("version 0\nstart now\nlet a = 1\n1>\n".to_string()).is_err());
+        assert!(Script::try_from("version 0\nstart now\n1>|1+1 0\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_assert() {
+        let script = "version 1
+start now
+macro check(d) {
+    +1>||assert pos 1 -2 3.5 within d
+}
+1>||assert unlocked
+check(0.5)
+";
+        let script = Script::try_from(script.to_string()).unwrap();
+        let tools: Vec<_> = script
+            .lines
+            .iter()
+            .map(|line| line.tools.clone().unwrap())
+            .collect();
+        assert_eq!(
+            tools,
+            vec![
+                vec![Tool::Assert(Assertion::Unlocked)],
+                vec![Tool::Assert(Assertion::Position {
+                    pos: Vec3 {
+                        x: 1.0,
+                        y: -2.0,
+                        z: 3.5
+                    },
+                    within: 0.5
+                })],
+            ]
+        );
+
+        for tools in [
+            "assert",
+            "assert pos 1 2 3",
+            "assert unlocked 1",
+            "assert pos 1 2 3 at 1",
+        ] {
+            let script = format!("version 1\nstart now\n1>||{tools}\n");
+            let errors = Script::try_from(script).unwrap_err();
+            assert_eq!(
+                errors[0].message,
+                "Expected `assert pos <x> <y> <z> within <distance>` or `assert unlocked`"
+            );
+        }
+    }
+
+    #[test]
+    fn test_tools() {
+        let script = "version 1\nstart now\n1>||setang 1.5 -0.25; lookat 10 -2 3.5\n";
+        let script = Script::try_from(script.to_string()).unwrap();
+        assert_eq!(
+            script.lines[0].tools,
+            Some(vec![
+                Tool::SetAng {
+                    ang: Vec2 { x: 1.5, y: -0.25 }
+                },
+                Tool::LookAt {
+                    target: Vec3 {
+                        x: 10.0,
+                        y: -2.0,
+                        z: 3.5
+                    }
+                },
+            ])
+        );
+
+        let errors =
+            Script::try_from("version 1\nstart now\n1>||lookat 1 2\n".to_string()).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Tool `lookat` expects 3 arguments, got 2"
+        );
+
+        let errors =
+            Script::try_from("version 1\nstart now\n1>||teleport\n".to_string()).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Unknown tool `teleport`, available tools: setpos, setang, lookat, assert"
+        );
+
+        // Only `setpos` exists in version 0
+        assert!(
+            Script::try_from("version 0\nstart now\n1>||setpos 1 2 3 0 0\n".to_string()).is_ok()
+        );
+        for tool in ["setang 1 2", "lookat 1 2 3", "assert unlocked"] {
+            let errors =
+                Script::try_from(format!("version 0\nstart now\n1>||{tool}\n")).unwrap_err();
+            let name = tool.split(' ').next().unwrap();
+            assert_eq!(
+                errors[0].message,
+                format!("Tool `{name}` was added in version 1, upgrade the script to use it")
+            );
+        }
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        let header = "version 1\nstart now\n1>\n";
+
+        let script = format!("{header}macro a(x) {{\n+1>|x 0\n}}\na(1, 2)\n");
+        let errors = Script::try_from(script).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 7:1: Macro `a` expects 1 arguments, got 2"
+        );
+
+        let script = format!("{header}macro a(x) {{\n+1>|y 0\n}}\na(1)\n");
+        let errors = Script::try_from(script).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 5:1: Unknown variable or parameter `y` (in macro `a` called at line 7)"
+        );
+
+        let script = format!("{header}macro a() {{\n+1>\nb()\n}}\nmacro b() {{\na()\n}}\na()\n");
+        let errors = Script::try_from(script).unwrap_err();
+        assert_eq!(errors[0].message, "Macros cannot call themselves");
+        assert_eq!(errors[0].line, 9);
+        assert_eq!(errors[0].context.len(), 2);
+
+        let script = format!("{header}c()\n");
+        let errors = Script::try_from(script).unwrap_err();
+        assert_eq!(errors[0].to_string(), "line 4:1: Unknown macro `c`");
+    }
+
+    #[test]
+    fn test_include() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("segments")).unwrap();
+
+        std::fs::write(
+            dir.join("main.wtas"),
+            "version 1\nstart now\n10>P\ninclude \"segments/a.wtas\"\n+5>p\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("segments/a.wtas"),
+            "+1>U\ninclude \"b.wtas\"\nclick()\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("segments/b.wtas"),
+            "macro click() {\n    +2>P\n}\n+2>u\n",
+        )
+        .unwrap();
+
+        let script = Script::from_file(&dir.join("main.wtas")).unwrap();
+        let ticks: Vec<u32> = script.lines.iter().map(|line| line.absolute_tick).collect();
+        assert_eq!(ticks, vec![10, 11, 13, 15, 20]);
+
+        // Errors in included files
+        std::fs::write(dir.join("segments/b.wtas"), "+2>u\n\n+1>?\n").unwrap();
+        let errors = Script::from_file(&dir.join("main.wtas")).unwrap_err();
+        assert!(errors[0].file.as_ref().unwrap().ends_with("b.wtas"));
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].context.len(), 2);
+
+        // Errors in macros defined in included files point to the macro body
+        std::fs::write(
+            dir.join("segments/b.wtas"),
+            "macro click() {\n    +0>P\n}\n+2>u\n",
+        )
+        .unwrap();
+        let errors = Script::from_file(&dir.join("main.wtas")).unwrap_err();
+        assert!(errors[0].file.as_ref().unwrap().ends_with("b.wtas"));
+        assert_eq!(errors[0].line, 2);
+        assert!(errors[0].context[0].starts_with("in macro `click` called at"));
+
+        // Include cycle
+        std::fs::write(dir.join("segments/b.wtas"), "+2>u\ninclude \"a.wtas\"\n").unwrap();
+        let errors = Script::from_file(&dir.join("main.wtas")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Include cycle detected");
+        assert!(errors[0].file.as_ref().unwrap().ends_with("b.wtas"));
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[0].context.len(), 2);
+
+        // Several segments can include the same macros
+        std::fs::write(
+            dir.join("segments/macros.wtas"),
+            "macro click() {\n    +2>P\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("segments/a.wtas"),
+            "include \"macros.wtas\"\nclick()\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("segments/b.wtas"),
+            "include \"macros.wtas\"\nclick()\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.wtas"),
+            "version 1\nstart now\n10>P\ninclude \"segments/a.wtas\"\ninclude \"segments/b.wtas\"\n\
+             include \"segments/macros.wtas\"\n+5>p\n",
+        )
+        .unwrap();
+        let script = Script::from_file(&dir.join("main.wtas")).unwrap();
+        let ticks: Vec<u32> = script.lines.iter().map(|line| line.absolute_tick).collect();
+        assert_eq!(ticks, vec![10, 12, 14, 19]);
+
+        // The same macro in two files is still a mistake
+        std::fs::write(
+            dir.join("segments/b.wtas"),
+            "macro click() {\n    +1>P\n}\n",
+        )
+        .unwrap();
+        let errors = Script::from_file(&dir.join("main.wtas")).unwrap_err();
+        assert_eq!(errors[0].message, "Macro `click` is already defined");
+    }
+}
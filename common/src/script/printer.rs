@@ -0,0 +1,830 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use super::{
+    BinaryOp, Expr, InputLine, KeyPress, Mouse, Script, ScriptLine, StartType, Statement, Tool,
+    ToolCall, Wait, LATEST_VERSION,
+};
+
+/// How the printer writes the ticks of the lines outside of blocks.
+/// The lines inside of blocks always keep their relative ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TickStyle {
+    /// Keep the ticks as they were written
+    #[default]
+    AsWritten,
+    /// Make all the ticks relative, except for the first line
+    Relative,
+    /// Make all the ticks absolute
+    Absolute,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    pub ticks: TickStyle,
+    /// Pad the fields of consecutive lines so that their `|` line up
+    pub align: bool,
+    pub comments: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            ticks: TickStyle::AsWritten,
+            align: false,
+            comments: true,
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Literal(num) => write!(f, "{num}"),
+            Expr::Param(name) => write!(f, "{name}"),
+            Expr::Neg(expr) => write!(f, "-{expr}"),
+            Expr::Paren(expr) => write!(f, "({expr})"),
+            Expr::Binary(op, lhs, rhs) => write!(f, "{lhs}{op}{rhs}"),
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        };
+        f.write_str(op)
+    }
+}
+
+impl Display for Mouse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mouse::Move(x, y) => write!(f, "{x} {y}"),
+            Mouse::Interpolate { curve, x, y } => write!(f, "{}({x}, {y})", curve.name()),
+        }
+    }
+}
+
+impl Display for Wait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Wait::PuzzleUnlock => write!(f, "wait puzzle_unlock"),
+            Wait::Interaction(status) => {
+                write!(f, "wait interaction == {}", Wait::interaction_name(*status))
+            }
+        }
+    }
+}
+
+impl Display for ToolCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.print(&PrintOptions::default()))
+    }
+}
+
+impl Script {
+    /// Writes the script back as text, in the canonical format
+    pub fn print(&self, options: &PrintOptions) -> String {
+        let mut header = vec![format!("version {}", self.version)];
+        header.extend(self.metadata.lines());
+        header.push(self.start_line());
+
+        self.print_after(header, options)
+    }
+
+    /// Writes the statements of the script, without its header
+    pub fn print_body(&self, options: &PrintOptions) -> String {
+        self.print_after(vec![], options)
+    }
+
+    fn print_after(&self, header: Vec<String>, options: &PrintOptions) -> String {
+        let mut printer = Printer {
+            options,
+            macros: HashMap::new(),
+            call_stack: vec![],
+            out: header,
+            tick: None,
+            after_wait: false,
+            mouse_reset: false,
+        };
+
+        printer.collect_macros(&self.statements);
+        printer.print_statements(&self.statements, 0);
+        printer.trim_blank_lines();
+        if printer.out.is_empty() {
+            return String::new();
+        }
+
+        let mut text = printer.out.join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// A script that starts now and plays `lines`, to write them back. Their ticks
+    /// are written as in the lines, and interpolations are written tick by tick.
+    pub fn from_lines(lines: &[ScriptLine]) -> Script {
+        let mut statements = vec![];
+        for line in lines {
+            for text in &line.comments {
+                statements.push(Statement::Comment {
+                    text: text.clone(),
+                    inline: false,
+                });
+            }
+            for wait in &line.waits {
+                statements.push(Statement::Wait {
+                    wait: *wait,
+                    span: 0..0,
+                });
+            }
+
+            let number = |value: i32| Expr::Literal(value.to_string());
+            statements.push(Statement::Line(InputLine {
+                relative: line.relative,
+                tick: line.tick,
+                end: None,
+                keys: line.keys.clone(),
+                mouse: line.mouse.map(|(x, y)| Mouse::Move(number(x), number(y))),
+                tools: line
+                    .tools
+                    .as_ref()
+                    .map(|tools| tools.iter().map(Tool::to_call).collect()),
+                span: 0..0,
+            }));
+
+            if let Some(text) = &line.inline_comment {
+                statements.push(Statement::Comment {
+                    text: text.clone(),
+                    inline: true,
+                });
+            }
+        }
+
+        Script {
+            version: LATEST_VERSION,
+            metadata: Default::default(),
+            start: StartType::Now,
+            statements,
+            lines: lines.to_vec(),
+            labels: vec![],
+            src: String::new(),
+            path: None,
+        }
+    }
+
+    fn start_line(&self) -> String {
+        match &self.start {
+            StartType::Now => "start now".to_string(),
+            StartType::NewGame => "start newgame".to_string(),
+            // Version 0 takes the rest of the line as the save name
+            StartType::Save(save) if self.version == 0 => format!("start save {save}"),
+            StartType::Save(save) => format!("start save \"{save}\""),
+        }
+    }
+}
+
+/// The fields of an input line, as they are written
+struct LineFields {
+    /// The tick and the keys
+    head: String,
+    mouse: Option<String>,
+    tools: Option<String>,
+}
+
+struct Printer<'a> {
+    options: &'a PrintOptions,
+    macros: HashMap<&'a str, &'a [Statement]>,
+    /// Names of the macros being skipped, to stop on recursive macros
+    call_stack: Vec<&'a str>,
+    out: Vec<String>,
+    /// Tick of the last line, used to convert the ticks
+    tick: Option<u32>,
+    /// Whether the next line follows a wait, and must keep its relative tick
+    after_wait: bool,
+    /// Whether the last line is an interpolation, whose mouse stops on the tick after it
+    mouse_reset: bool,
+}
+
+impl<'a> Printer<'a> {
+    /// Registers the macros defined in the statements, and in the included files
+    fn collect_macros(&mut self, statements: &'a [Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Macro { name, body, .. } => {
+                    self.macros.insert(name, body);
+                }
+                Statement::Include {
+                    file: Some(file), ..
+                } => self.collect_macros(&file.statements),
+                _ => {}
+            }
+        }
+    }
+
+    /// Computes the absolute ticks of the start and the end of the line,
+    /// and makes the end the last tick
+    fn resolve(&mut self, line: &InputLine) -> (u32, u32) {
+        let absolute = |relative, tick| match self.tick {
+            Some(previous) if relative => previous + tick,
+            _ => tick,
+        };
+        let start = absolute(line.relative, line.tick);
+        let end = match line.end {
+            Some((relative, end)) => absolute(relative, end),
+            None => start,
+        };
+        self.tick = Some(end);
+        self.after_wait = false;
+        self.mouse_reset =
+            line.end.is_some() && matches!(line.mouse, Some(Mouse::Interpolate { .. }));
+
+        (start, end)
+    }
+
+    /// Follows a wait. The mouse stops before it, on a line the next one counts from.
+    fn wait(&mut self) {
+        if std::mem::take(&mut self.mouse_reset) {
+            self.tick = self.tick.map(|tick| tick + 1);
+        }
+        self.after_wait = true;
+    }
+
+    /// Follows the ticks of the statements without printing them
+    fn skip(&mut self, statements: &'a [Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Line(line) => {
+                    self.resolve(line);
+                }
+                Statement::Repeat { count, body, .. } => {
+                    for _ in 0..*count {
+                        self.skip(body);
+                    }
+                }
+                Statement::Call { name, .. } => {
+                    let Some(&body) = self.macros.get(name.as_str()) else {
+                        continue;
+                    };
+
+                    if !self.call_stack.contains(&name.as_str()) {
+                        self.call_stack.push(name);
+                        self.skip(body);
+                        self.call_stack.pop();
+                    }
+                }
+                Statement::Include {
+                    file: Some(file), ..
+                } => self.skip(&file.statements),
+                Statement::Wait { .. } => self.wait(),
+                _ => {}
+            }
+        }
+    }
+
+    fn push(&mut self, depth: usize, text: impl Display) {
+        self.out
+            .push(format!("{:indent$}{text}", "", indent = depth * 4));
+    }
+
+    /// Removes the blank lines at the end, which are not between two statements
+    fn trim_blank_lines(&mut self) {
+        while self.out.last().is_some_and(String::is_empty) {
+            self.out.pop();
+        }
+    }
+
+    /// Adds a comment at the end of the last line
+    fn push_inline_comment(&mut self, text: &str, width: usize) {
+        match self.out.last_mut() {
+            Some(last) => *last = format!("{last:width$} //{text}"),
+            None => self.out.push(format!("//{text}")),
+        }
+    }
+
+    fn print_statements(&mut self, statements: &'a [Statement], depth: usize) {
+        // Comments on their own line separate the lines, unless they are removed
+        let comments = self.options.comments;
+        let is_line = |statement: &Statement| match statement {
+            Statement::Line(_) => true,
+            Statement::Comment { inline, .. } => *inline || !comments,
+            _ => false,
+        };
+
+        let mut rest = statements;
+        while let Some(statement) = rest.first() {
+            // Consecutive lines are printed together to be aligned
+            let lines = rest.iter().take_while(|s| is_line(s)).count();
+            if lines > 0 {
+                self.print_lines(&rest[..lines], depth);
+                rest = &rest[lines..];
+            } else {
+                self.print_statement(statement, depth);
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    fn print_statement(&mut self, statement: &'a Statement, depth: usize) {
+        match statement {
+            Statement::Line(_) => self.print_lines(std::slice::from_ref(statement), depth),
+            Statement::Repeat { count, body, .. } => {
+                self.push(depth, format!("repeat {count} {{"));
+                self.print_statements(body, depth + 1);
+                self.trim_blank_lines();
+                self.push(depth, "}");
+
+                // The body was printed once, follow the ticks of the other iterations
+                for _ in 1..*count {
+                    self.skip(body);
+                }
+            }
+            Statement::Macro {
+                name, params, body, ..
+            } => {
+                self.push(depth, format!("macro {name}({}) {{", params.join(", ")));
+                let tick = self.tick;
+                self.print_statements(body, depth + 1);
+                self.tick = tick;
+                self.trim_blank_lines();
+                self.push(depth, "}");
+            }
+            Statement::Call { name, args, .. } => {
+                let args: Vec<_> = args.iter().map(Expr::to_string).collect();
+                self.push(depth, format!("{name}({})", args.join(", ")));
+                self.skip(std::slice::from_ref(statement));
+            }
+            Statement::Include { path, file, .. } => {
+                self.push(depth, format!("include \"{path}\""));
+                if let Some(file) = file {
+                    self.skip(&file.statements);
+                }
+            }
+            Statement::Comment { text, inline } => {
+                if !self.options.comments {
+                    return;
+                }
+
+                if *inline {
+                    self.push_inline_comment(text, 0);
+                } else {
+                    self.push(depth, format!("//{text}"));
+                }
+            }
+            Statement::Wait { wait, .. } => {
+                self.push(depth, wait);
+                self.wait();
+            }
+            Statement::Label { name, .. } => self.push(depth, format!("@label {name}")),
+            Statement::Let { name, value, .. } => self.push(depth, format!("let {name} = {value}")),
+            Statement::Blank => {
+                // Only keep blank lines between two statements
+                let previous = self.out.last().map(|line| line.trim_end());
+                if previous.is_some_and(|line| !line.is_empty() && !line.ends_with('{')) {
+                    self.out.push(String::new());
+                }
+            }
+        }
+    }
+
+    fn line_fields(&mut self, line: &InputLine, depth: usize) -> LineFields {
+        let previous = self.tick;
+        let after_wait = self.after_wait;
+        let (start, end) = self.resolve(line);
+
+        // Ticks in blocks and after waits must stay relative
+        let style = match depth {
+            0 if !after_wait => self.options.ticks,
+            _ => TickStyle::AsWritten,
+        };
+        let format_tick = |tick, relative, written| match (style, previous) {
+            (TickStyle::Absolute, _) => format!("{tick}"),
+            (TickStyle::Relative, Some(previous)) => format!("+{}", tick - previous),
+            _ if relative => format!("+{written}"),
+            _ => format!("{written}"),
+        };
+        let mut tick = format_tick(start, line.relative, line.tick);
+        if let Some((relative, written)) = line.end {
+            tick = format!("{tick}..{}", format_tick(end, relative, written));
+        }
+
+        let keys: String = line.keys.iter().map(KeyPress::to_string).collect();
+        let mouse = line.mouse.as_ref().map(Mouse::to_string);
+        let tools = line.tools.as_ref().map(|tools| {
+            let tools: Vec<_> = tools.iter().map(ToolCall::to_string).collect();
+            tools.join("; ")
+        });
+
+        LineFields {
+            head: format!("{tick}>{keys}"),
+            mouse,
+            tools,
+        }
+    }
+
+    /// Prints consecutive lines, along with their inline comments
+    fn print_lines(&mut self, statements: &'a [Statement], depth: usize) {
+        let fields: Vec<_> = statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Line(line) => Some(self.line_fields(line, depth)),
+                _ => None,
+            })
+            .collect();
+
+        // Width of the fields before each `|`
+        let (mut head_width, mut mouse_width) = (0, 0);
+        if self.options.align {
+            for line in fields.iter().flatten() {
+                if line.mouse.is_some() || line.tools.is_some() {
+                    head_width = head_width.max(line.head.len());
+                }
+                if line.tools.is_some() {
+                    mouse_width = mouse_width.max(line.mouse.as_ref().map_or(0, String::len));
+                }
+            }
+        }
+
+        let texts: Vec<_> = fields
+            .iter()
+            .map(|line| {
+                let line = line.as_ref()?;
+                let mut text = line.head.clone();
+                if line.mouse.is_some() || line.tools.is_some() {
+                    let mouse = line.mouse.as_deref().unwrap_or_default();
+                    text = format!("{text:head_width$}|{mouse}");
+                }
+                if let Some(tools) = &line.tools {
+                    text = format!(
+                        "{text:width$}|{tools}",
+                        width = head_width + 1 + mouse_width
+                    );
+                }
+                Some(text)
+            })
+            .collect();
+
+        // Width of the lines before an inline comment
+        let mut comment_width = 0;
+        if self.options.align {
+            for (text, statement) in texts.iter().zip(statements.iter().skip(1)) {
+                if let (Some(text), Statement::Comment { .. }) = (text, statement) {
+                    comment_width = comment_width.max(text.len() + depth * 4);
+                }
+            }
+        }
+
+        for (text, statement) in texts.into_iter().zip(statements) {
+            match (text, statement) {
+                (Some(text), _) => self.push(depth, text),
+                (None, Statement::Comment { text, .. }) if self.options.comments => {
+                    self.push_inline_comment(text, comment_width);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PrintOptions, TickStyle};
+    use crate::script::{
+        Curve, Expr, InputLine, KeyPress, Mouse, Script, ScriptLine, StartType, Statement,
+        ToolCall, KEYS,
+    };
+    use proptest::prelude::*;
+
+    /// What is played by the script, regardless of how it is written
+    fn played(script: &Script) -> Vec<ScriptLine> {
+        script
+            .lines
+            .iter()
+            .map(|line| ScriptLine {
+                relative: false,
+                tick: line.absolute_tick,
+                span: 0..0,
+                comments: vec![],
+                inline_comment: None,
+                ..line.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_print() {
+        let script = "version 1
+// start of the tas
+start now
+
+1>p
+  3>p  // comment
++1>|-300   -300//another one
++1>||setpos 1 2 3 0 0;setpos 4 5 6 0 0
+macro m(x,y){
++1>|x y
+repeat 2 {// inline
++1>P
+
+
+}
+}
+
+
+10>
+m(1,-2)
+";
+
+        let script = Script::try_from(script.to_string()).unwrap();
+        assert_eq!(
+            script.to_string(),
+            "version 1
+start now
+// start of the tas
+
+1>p
+3>p // comment
++1>|-300 -300 //another one
++1>||setpos 1 2 3 0 0; setpos 4 5 6 0 0
+macro m(x, y) {
+    +1>|x y
+    repeat 2 { // inline
+        +1>P
+    }
+}
+
+10>
+m(1, -2)
+"
+        );
+
+        let options = PrintOptions {
+            ticks: TickStyle::Absolute,
+            align: true,
+            comments: false,
+        };
+        assert_eq!(
+            script.print(&options),
+            "version 1
+start now
+
+1>p
+3>p
+4>|-300 -300
+5>||setpos 1 2 3 0 0; setpos 4 5 6 0 0
+macro m(x, y) {
+    +1>|x y
+    repeat 2 {
+        +1>P
+    }
+}
+
+10>
+m(1, -2)
+"
+        );
+
+        let aligned = "version 0\nstart now\n1>P|10 10\n+20>|1 2|setpos 1 2 3 0 0\n+1>U // a\n+2>|-300 0 // b\n";
+        let options = PrintOptions {
+            align: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Script::try_from(aligned.to_string())
+                .unwrap()
+                .print(&options),
+            "version 0
+start now
+1>P |10 10
++20>|1 2|setpos 1 2 3 0 0
++1>U        // a
++2> |-300 0 // b
+"
+        );
+
+        let options = PrintOptions {
+            ticks: TickStyle::Relative,
+            ..Default::default()
+        };
+        let text = script.print(&options);
+        assert!(text.contains("\n1>p\n+2>p // comment\n"), "{text}");
+        assert!(text.contains("\n+5>\nm(1, -2)\n"), "{text}");
+
+        // The end of a range is relative to the previous line, like its start
+        let ranges = "version 1\nstart now\n5>\n+1..+20>|ease(300, -40)\n30..+10>|linear(1, 2)\n";
+        let script = Script::try_from(ranges.to_string()).unwrap();
+        assert_eq!(script.to_string(), ranges);
+        let options = PrintOptions {
+            ticks: TickStyle::Absolute,
+            ..Default::default()
+        };
+        assert!(script
+            .print(&options)
+            .ends_with("\n6..25>|ease(300, -40)\n30..35>|linear(1, 2)\n"));
+    }
+
+    #[test]
+    fn test_print_waits() {
+        // The mouse stops on the tick after the range, and the line after the wait counts from it
+        let src = "version 1\nstart now\n1..5>|linear(10, 0)\nwait puzzle_unlock\n+1>P\n+2>p\n";
+        let script = Script::try_from(src.to_string()).unwrap();
+        for ticks in [TickStyle::Absolute, TickStyle::Relative] {
+            let options = PrintOptions {
+                ticks,
+                ..Default::default()
+            };
+            let text = script.print(&options);
+            let reparsed = Script::try_from(text.clone()).unwrap();
+            assert_eq!(played(&reparsed), played(&script), "{text}");
+        }
+
+        let options = PrintOptions {
+            ticks: TickStyle::Absolute,
+            ..Default::default()
+        };
+        assert!(script
+            .print(&options)
+            .ends_with("\nwait puzzle_unlock\n+1>P\n9>p\n"));
+    }
+
+    #[test]
+    fn test_print_example() {
+        let src = include_str!("../../../example.wtas");
+        let script = Script::try_from(src.to_string()).unwrap();
+        let text = script.to_string();
+
+        let reparsed = Script::try_from(text.clone()).unwrap();
+        assert_eq!(played(&reparsed), played(&script));
+        assert_eq!(reparsed.to_string(), text);
+    }
+
+    fn number() -> impl Strategy<Value = Expr> {
+        (-1000i32..1000, 0u32..100)
+            .prop_map(|(int, fract)| Expr::Literal(format!("{int}.{fract:02}")))
+    }
+
+    fn comment() -> impl Strategy<Value = String> {
+        "[ a-zA-Z0-9_.,/]{0,12}"
+    }
+
+    /// An input line with the given tick
+    fn line(relative: bool, tick: u32) -> impl Strategy<Value = InputLine> {
+        let keys = prop::collection::vec(
+            prop::sample::select(KEYS).prop_map(|key| KeyPress { key, hold: None }),
+            0..4,
+        );
+        let mouse = prop::option::of((-500i32..500, -500i32..500));
+        let setpos = prop::collection::vec(number(), 5).prop_map(|args| ToolCall {
+            name: "setpos".to_string(),
+            args,
+            span: 0..0,
+        });
+        let tools = prop::option::of(prop::collection::vec(setpos, 0..3));
+
+        (keys, mouse, tools).prop_map(move |(keys, mouse, tools)| InputLine {
+            relative,
+            tick,
+            end: None,
+            keys,
+            mouse: mouse.map(|(x, y)| {
+                Mouse::Move(Expr::Literal(x.to_string()), Expr::Literal(y.to_string()))
+            }),
+            tools,
+            span: 0..0,
+        })
+    }
+
+    /// An interpolated line, whose range ends `end` ticks after the previous line
+    fn interpolated_line(end: u32) -> impl Strategy<Value = InputLine> {
+        let curve = prop::sample::select(vec![Curve::Linear, Curve::Ease]);
+        (1..=end, curve, -500i32..500, -500i32..500).prop_flat_map(move |(tick, curve, x, y)| {
+            line(true, tick).prop_map(move |line| InputLine {
+                end: Some((true, end)),
+                mouse: Some(Mouse::Interpolate {
+                    curve,
+                    x: Expr::Literal(x.to_string()),
+                    y: Expr::Literal(y.to_string()),
+                }),
+                ..line
+            })
+        })
+    }
+
+    /// A statement, that lasts `duration` ticks
+    fn statement(duration: u32) -> impl Strategy<Value = (Statement, u32)> {
+        let relative_line =
+            line(true, duration).prop_map(move |line| (Statement::Line(line), duration));
+        let interpolated_line =
+            interpolated_line(duration).prop_map(move |line| (Statement::Line(line), duration));
+        let repeat =
+            (1u32..4, prop::collection::vec(1u32..5, 1..4)).prop_flat_map(|(count, ticks)| {
+                let duration = count * ticks.iter().sum::<u32>();
+                let body: Vec<_> = ticks
+                    .into_iter()
+                    .map(|tick| line(true, tick).prop_map(Statement::Line))
+                    .collect();
+                body.prop_map(move |body| {
+                    let span = 0..0;
+                    (Statement::Repeat { count, body, span }, duration)
+                })
+            });
+        let comment = (comment(), any::<bool>())
+            .prop_map(|(text, inline)| (Statement::Comment { text, inline }, 0));
+
+        prop_oneof![
+            4 => relative_line,
+            1 => interpolated_line,
+            1 => repeat,
+            1 => comment,
+            1 => Just((Statement::Blank, 0)),
+        ]
+    }
+
+    /// A valid script, made of lines with increasing ticks
+    fn script() -> impl Strategy<Value = Script> {
+        // Scripts start with an absolute line
+        let first = (1u32..30).prop_flat_map(|tick| line(false, tick));
+        let statements = prop::collection::vec(
+            (1u32..30, any::<bool>())
+                .prop_flat_map(|(tick, absolute)| (statement(tick), Just(absolute))),
+            0..20,
+        );
+
+        (first, statements).prop_map(|(first, statements)| {
+            let mut tick = first.tick;
+            // Inline comments and blank lines must follow a statement
+            let mut after_statement = true;
+            let mut body = vec![Statement::Line(first)];
+
+            for ((mut statement, duration), absolute) in statements {
+                match &mut statement {
+                    // Some lines use absolute ticks
+                    Statement::Line(line) if absolute => {
+                        line.relative = false;
+                        line.tick += tick;
+                        if let Some((relative, end)) = &mut line.end {
+                            *relative = false;
+                            *end += tick;
+                        }
+                    }
+                    Statement::Comment { inline, .. } => *inline &= after_statement,
+                    Statement::Blank if !after_statement => continue,
+                    _ => {}
+                }
+
+                tick += duration;
+                after_statement =
+                    !matches!(statement, Statement::Comment { .. } | Statement::Blank);
+                body.push(statement);
+            }
+
+            let mut script = Script {
+                version: 1,
+                metadata: Default::default(),
+                start: StartType::Now,
+                statements: body,
+                lines: vec![],
+                labels: vec![],
+                src: String::new(),
+                path: None,
+            };
+            script.pre_process("", None).unwrap();
+            script
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(
+            script in script(),
+            ticks in prop::sample::select(vec![TickStyle::AsWritten, TickStyle::Relative, TickStyle::Absolute]),
+            align in any::<bool>(),
+            comments in any::<bool>(),
+        ) {
+            let options = PrintOptions { ticks, align, comments };
+            let text = script.print(&options);
+
+            let parsed = Script::try_from(text.clone());
+            prop_assert!(parsed.is_ok(), "{text}\n{:?}", parsed.unwrap_err());
+            let parsed = parsed.unwrap();
+
+            prop_assert_eq!(played(&parsed), played(&script));
+            prop_assert_eq!(parsed.print(&options), text);
+        }
+    }
+}
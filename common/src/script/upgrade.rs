@@ -0,0 +1,120 @@
+use chumsky::Parser;
+
+use super::{PrintOptions, Script, ScriptError, StartType, LATEST_VERSION};
+
+impl Script {
+    /// Rewrites a script in the latest version of the format, keeping what it plays.
+    /// Scripts that already use the latest version are returned as is.
+    /// Older versions cannot include other files, so there is only this one to rewrite.
+    pub fn upgrade(src: &str) -> Result<String, Vec<ScriptError>> {
+        let mut script = Self::get_parser().parse(src).map_err(|parse_errs| {
+            parse_errs
+                .iter()
+                .map(|e| ScriptError::from_parse_error(e, src, None))
+                .collect::<Vec<_>>()
+        })?;
+
+        if script.version == LATEST_VERSION {
+            return Ok(src.to_string());
+        }
+
+        // Quoted save names cannot contain quotes
+        if let StartType::Save(save) = &script.start {
+            if save.contains('"') {
+                let start = src[..src.find(save.as_str()).unwrap_or(0)].chars().count();
+                let error = ScriptError::new(
+                    format!("The save name `{save}` contains a `\"`, it cannot be quoted"),
+                    start..start + save.chars().count(),
+                    src,
+                    None,
+                )
+                .with_hint("Rename the save without the `\"` before upgrading");
+                return Err(vec![error]);
+            }
+        }
+
+        // The printer writes the script with the syntax of its version
+        script.version = LATEST_VERSION;
+        Ok(script.print(&PrintOptions::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{Script, StartType, LATEST_VERSION};
+
+    #[test]
+    fn test_upgrade() {
+        let src = "version 0
+start save tutorial.witness_campaign
+
+// Solve start panel
+6>P
++1>|-15 7 // cursor
++1>P
++1>p
+";
+
+        let upgraded = Script::upgrade(src).unwrap();
+        assert_eq!(
+            upgraded,
+            "version 1
+start save \"tutorial.witness_campaign\"
+
+// Solve start panel
+6>P
++1>|-15 7 // cursor
++1>P
++1>p
+"
+        );
+
+        let old = Script::try_from(src.to_string()).unwrap();
+        let new = Script::try_from(upgraded.clone()).unwrap();
+        assert_eq!(new.version, LATEST_VERSION);
+        let played = |script: &Script| {
+            let lines = script.lines.iter();
+            lines
+                .map(|line| (line.absolute_tick, line.keys.clone(), line.mouse))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(played(&new), played(&old));
+        let StartType::Save(save) = new.start else {
+            panic!("Expected a save")
+        };
+        assert_eq!(save, "tutorial.witness_campaign");
+
+        // Already up to date
+        assert_eq!(Script::upgrade(&upgraded).unwrap(), upgraded);
+
+        let errors = Script::upgrade("version 0\nstart save my \"best\" save\n1>\n").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 2:12: The save name `my \"best\" save` contains a `\"`, it cannot be quoted"
+        );
+    }
+
+    #[test]
+    fn test_versions() {
+        // Version 0 reads the save name until the end of the line
+        let script = Script::try_from("version 0\nstart save my save\n1>\n".to_string()).unwrap();
+        assert!(matches!(script.start, StartType::Save(save) if save == "my save"));
+
+        let script =
+            Script::try_from("version 1\nstart save \"my save\" // comment\n1>\n".to_string())
+                .unwrap();
+        assert!(matches!(script.start, StartType::Save(save) if save == "my save"));
+
+        assert!(Script::try_from("version 1\nstart save my save\n1>\n".to_string()).is_err());
+
+        // Repeat blocks, macros and includes came with version 1
+        for statement in [
+            "repeat 2 {\n    +1>P\n}",
+            "macro m() {\n    +1>P\n}",
+            "include \"a.wtas\"",
+        ] {
+            let src = format!("version 0\nstart now\n1>\n{statement}\n");
+            assert!(Script::try_from(src).is_err(), "{statement}");
+        }
+    }
+}
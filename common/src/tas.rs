@@ -54,6 +54,17 @@ impl Default for TraceDrawOptions {
     }
 }
 
+/// A failed assertion of the script, meaning that the playback desynced
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AssertionFailure {
+    /// The tick the assertion ran on
+    pub tick: u32,
+    /// What the script expected
+    pub expected: String,
+    /// What happened in the game instead
+    pub actual: String,
+}
+
 pub fn to_time(ticks: u32) -> String {
     let total_secs = ticks as f32 / 60.0;
     let mins = (total_secs / 60.0).floor() as u32;
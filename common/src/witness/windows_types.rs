@@ -54,11 +54,14 @@ pub struct MSG {
 // Some useful defs
 back_to_enum! {
     #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub enum VirtualKeyCode {
         // Mouse
         LButton = 0x01,
         RButton = 0x02,
 
+        Tab = 0x09,
+        Return = 0x0D,
         ESC = 0x1B,
         Space = 0x20,
 
@@ -24,6 +24,18 @@ impl std::ops::Add for Vec3 {
     }
 }
 
+impl std::ops::Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
 impl std::ops::Mul<f32> for Vec3 {
     type Output = Self;
 
@@ -49,6 +61,16 @@ impl Vec3 {
             z: self.z / len,
         }
     }
+
+    /// Returns the camera angles (yaw, pitch) that look along this direction.
+    /// The yaw is 0 towards +x and turns towards +y, the pitch is positive upwards.
+    pub fn to_angles(&self) -> Vec2 {
+        let horizontal = (self.x * self.x + self.y * self.y).sqrt();
+        Vec2 {
+            x: self.y.atan2(self.x),
+            y: self.z.atan2(horizontal),
+        }
+    }
 }
 
 #[repr(C)]
@@ -118,6 +140,7 @@ pub struct Entity {
 
 back_to_enum! {
     #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum InteractionStatus {
         FocusMode = 0x0,
         SolvingPanel = 0x1,
@@ -125,3 +148,23 @@ back_to_enum! {
         Cinematic = 0x3,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Vec3;
+
+    #[test]
+    fn test_to_angles() {
+        let angles = |x, y, z| {
+            let ang = Vec3 { x, y, z }.to_angles();
+            (ang.x, ang.y)
+        };
+
+        assert_eq!(angles(1.0, 0.0, 0.0), (0.0, 0.0));
+        assert_eq!(angles(0.0, 2.0, 0.0), (std::f32::consts::FRAC_PI_2, 0.0));
+        assert_eq!(
+            angles(-3.0, 0.0, 3.0),
+            (std::f32::consts::PI, std::f32::consts::FRAC_PI_4)
+        );
+    }
+}
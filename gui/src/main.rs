@@ -3,13 +3,14 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 use eframe::{run_native, App};
 use egui::Ui;
 use common::communication::{client_thread, ControllerToTasMessage, TasToControllerMessage};
-use common::tas::{to_time, PlaybackState, TraceDrawOptions, TraceInterval};
+use common::script::{Label, LintWarning, Metadata, ScriptError, SettingCheck, Severity};
+use common::tas::{to_time, AssertionFailure, PlaybackState, TraceDrawOptions, TraceInterval};
 
 mod platform;
 use platform::try_inject;
 
 mod widgets;
-use widgets::scrollable_dragvalue;
+use widgets::{label_combobox, lint_warning, script_error, scrollable_dragvalue};
 
 #[derive(PartialEq)]
 enum TasInterfaceTab {
@@ -30,14 +31,37 @@ struct TasInterface {
     looping: bool,
     skipto: u32,
     pauseat: u32,
+    // When set, the labels are used instead of the ticks
+    skipto_label: Option<String>,
+    pauseat_label: Option<String>,
     always_pause_after_skip: bool,
+    pause_on_assertion_failure: bool,
+    legit_mode: bool,
+    record_filename: String,
+    recording: bool,
+    append_to_script: bool,
+    savestate_slot: u32,
 
     // Info
     player_pos: (f32, f32, f32), // Replace with vec3
     player_ang: (f32, f32),      // Replace with vec2
     current_tick: u32,
     latest_puzzle_unlock: u32,
-    parse_errors: Vec<String>,
+    parse_errors: Vec<ScriptError>,
+    lint_warnings: Vec<LintWarning>,
+    script_metadata: Metadata,
+    setting_checks: Vec<SettingCheck>,
+    assertion_failures: Vec<AssertionFailure>,
+    labels: Vec<Label>,
+    recorded_lines: String,
+    takeover_error: Option<String>,
+    /// The first tick the reloaded script plays differently on
+    diverged_at: Option<u32>,
+    /// What happened to the last savestate saved or loaded
+    savestate_info: Option<String>,
+    savestate_error: Option<String>,
+    /// The state of the game that savestates do not capture
+    not_captured: Vec<String>,
 
     // Trace
     trace_selected_tick: u32,
@@ -62,12 +86,31 @@ impl TasInterface {
             looping: false,
             skipto: 0,
             pauseat: 0,
+            skipto_label: None,
+            pauseat_label: None,
             always_pause_after_skip: false,
+            pause_on_assertion_failure: false,
+            legit_mode: false,
+            record_filename: "recording.wtas".to_string(),
+            recording: false,
+            append_to_script: false,
+            savestate_slot: 1,
             player_pos: (0., 0., 0.),
             player_ang: (0., 0.),
             current_tick: 0,
             latest_puzzle_unlock: 0,
             parse_errors: vec![],
+            lint_warnings: vec![],
+            script_metadata: Default::default(),
+            setting_checks: vec![],
+            assertion_failures: vec![],
+            labels: vec![],
+            recorded_lines: String::new(),
+            takeover_error: None,
+            diverged_at: None,
+            savestate_info: None,
+            savestate_error: None,
+            not_captured: vec![],
             trace_selected_tick: 0,
             trace_continuous_teleport: false,
             trace_display_opts: Default::default(),
@@ -84,9 +127,21 @@ impl TasInterface {
         self.from_server = from_server;
 
         // TODO: resend info to server
-        self.to_server
-            .send(ControllerToTasMessage::SkipTo(self.skipto))
-            .unwrap();
+        self.to_server.send(self.skipto_message()).unwrap();
+    }
+
+    fn skipto_message(&self) -> ControllerToTasMessage {
+        match &self.skipto_label {
+            Some(label) => ControllerToTasMessage::SkipToLabel(label.clone()),
+            None => ControllerToTasMessage::SkipTo(self.skipto),
+        }
+    }
+
+    fn pauseat_message(&self) -> ControllerToTasMessage {
+        match &self.pauseat_label {
+            Some(label) => ControllerToTasMessage::PauseAtLabel(label.clone()),
+            None => ControllerToTasMessage::PauseAt(self.pauseat),
+        }
     }
 
     fn update_from_server(&mut self) {
@@ -100,12 +155,50 @@ impl TasInterface {
                     }
                 }
                 TasToControllerMessage::CurrentTick(tick) => self.current_tick = tick,
-                TasToControllerMessage::ParseErrors(errors) => self.parse_errors = errors,
+                TasToControllerMessage::ParseErrors(errors) => {
+                    self.parse_errors = errors;
+                    self.lint_warnings.clear();
+                    self.script_metadata = Default::default();
+                    self.setting_checks.clear();
+                    self.labels.clear();
+                }
+                // Sent after the script is parsed
+                TasToControllerMessage::LintWarnings(lints) => self.lint_warnings = lints,
+                TasToControllerMessage::Labels(labels) => self.labels = labels,
+                // Sent when a script starts
+                TasToControllerMessage::ScriptMetadata(metadata) => {
+                    self.script_metadata = metadata;
+                    self.assertion_failures.clear();
+                }
+                TasToControllerMessage::SettingChecks(checks) => self.setting_checks = checks,
                 TasToControllerMessage::CarlInfo { pos, ang } => {
                     self.player_pos = pos;
                     self.player_ang = ang;
                 }
                 TasToControllerMessage::PuzzleUnlock(tick) => self.latest_puzzle_unlock = tick,
+                TasToControllerMessage::AssertionFailed(failure) => {
+                    self.assertion_failures.push(failure)
+                }
+                TasToControllerMessage::RecordedLines(text) => self.recorded_lines = text,
+                TasToControllerMessage::TakeoverError(err) => {
+                    self.takeover_error = Some(err);
+                    self.recording = false;
+                }
+                TasToControllerMessage::Reloaded { diverged_at } => self.diverged_at = diverged_at,
+                TasToControllerMessage::StateSaved {
+                    slot,
+                    tick,
+                    not_captured,
+                } => {
+                    self.savestate_info = Some(format!("Saved slot {slot} on tick {tick}"));
+                    self.savestate_error = None;
+                    self.not_captured = not_captured;
+                }
+                TasToControllerMessage::StateLoaded { slot, tick } => {
+                    self.savestate_info = Some(format!("Loaded slot {slot}, back on tick {tick}"));
+                    self.savestate_error = None;
+                }
+                TasToControllerMessage::SavestateError(err) => self.savestate_error = Some(err),
             }
         }
     }
@@ -118,11 +211,7 @@ impl App for TasInterface {
         // App
         egui::CentralPanel::default().show(ctx, |ui| {
             // Try to send a no-op to see if channel is alive
-            if self
-                .to_server
-                .send(ControllerToTasMessage::SkipTo(self.skipto))
-                .is_err()
-            {
+            if self.to_server.send(self.skipto_message()).is_err() {
                 ui.label(
                     "Failed to connect to The Witness! Open the game and press the button below.",
                 );
@@ -185,13 +274,101 @@ impl TasInterface {
             self.latest_puzzle_unlock
         ));
 
+        self.script_metadata(ui);
+
+        if !self.setting_checks.is_empty() {
+            ui.heading("Game settings");
+            for check in &self.setting_checks {
+                let color = match check.severity {
+                    Severity::Error => ui.visuals().error_fg_color,
+                    Severity::Warning => ui.visuals().warn_fg_color,
+                };
+                ui.colored_label(color, &check.message);
+            }
+        }
+
+        if !self.assertion_failures.is_empty() {
+            ui.heading("Failed assertions");
+            for failure in &self.assertion_failures {
+                ui.horizontal_wrapped(|ui| {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("tick {}", failure.tick),
+                    );
+                    ui.label(format!(
+                        "expected {}, got {}",
+                        failure.expected, failure.actual
+                    ));
+                });
+            }
+        }
+
         if !self.parse_errors.is_empty() {
             ui.heading("Parse errors");
             for error in &self.parse_errors {
-                ui.label(error);
+                ui.add(script_error(error));
             }
         }
 
+        if !self.lint_warnings.is_empty() {
+            ui.heading("Warnings");
+            for lint in &self.lint_warnings {
+                ui.add(lint_warning(lint));
+            }
+        }
+
+        if let Some(err) = &self.takeover_error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+
+        if let Some(err) = &self.savestate_error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        } else if let Some(info) = &self.savestate_info {
+            ui.label(info).on_hover_ui(|ui| {
+                ui.label("Savestates do not capture:");
+                for state in &self.not_captured {
+                    ui.label(format!("- {state}"));
+                }
+            });
+        }
+
+        if let Some(tick) = self.diverged_at {
+            ui.horizontal_wrapped(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("The script changed on tick {tick}, the old one keeps playing"),
+                );
+                if ui
+                    .button("Restart")
+                    .on_hover_text("Skip to that tick and pause there")
+                    .clicked()
+                {
+                    self.skipto = tick;
+                    self.skipto_label = None;
+                    self.pauseat = tick;
+                    self.pauseat_label = None;
+                    self.diverged_at = None;
+                    for msg in [
+                        self.skipto_message(),
+                        self.pauseat_message(),
+                        ControllerToTasMessage::Stop,
+                        ControllerToTasMessage::PlayFile(self.filename.clone()),
+                    ] {
+                        self.to_server.send(msg).unwrap();
+                    }
+                }
+            });
+        }
+
+        if !self.recorded_lines.is_empty() {
+            ui.collapsing("Recorded lines", |ui| {
+                ui.label(egui::RichText::new(&self.recorded_lines).monospace());
+                if ui.button("Copy").clicked() {
+                    ui.output_mut(|o| o.copied_text = self.recorded_lines.clone());
+                }
+            });
+        }
+
         // Paste exact pos/ang into clipboard on click
         if pos.clicked() {
             ui.output_mut(|o| {
@@ -208,6 +385,36 @@ impl TasInterface {
         }
     }
 
+    /// Draw the metadata of the last started script, if it has any
+    fn script_metadata(&self, ui: &mut Ui) {
+        let metadata = &self.script_metadata;
+        if *metadata == Metadata::default() {
+            return;
+        }
+
+        let title = match (&metadata.category, &metadata.author) {
+            (Some(category), Some(author)) => format!("Script: {category} by {author}"),
+            (Some(category), None) => format!("Script: {category}"),
+            (None, Some(author)) => format!("Script by {author}"),
+            (None, None) => "Script".to_string(),
+        };
+
+        ui.collapsing(title, |ui| {
+            if let Some((width, height)) = metadata.resolution {
+                ui.label(format!("Resolution: {width}x{height}"));
+            }
+            if let Some(fov) = metadata.fov {
+                ui.label(format!("FOV: {fov}"));
+            }
+            if let Some(sensitivity) = metadata.mouse_sensitivity {
+                ui.label(format!("Mouse sensitivity: {sensitivity}"));
+            }
+            if let Some(version) = &metadata.game_version {
+                ui.label(format!("Game version: {version}"));
+            }
+        });
+    }
+
     /// Draw the playback controls
     fn playback_controls_tab(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
@@ -234,6 +441,14 @@ impl TasInterface {
                     self.to_server.send(ControllerToTasMessage::Stop).unwrap();
                 }
 
+                if ui
+                    .add_enabled(stop_button_enabled, egui::Button::new("Reload"))
+                    .on_hover_text("Keep playing the edited script, if it did not change before the current tick")
+                    .clicked()
+                {
+                    self.to_server.send(ControllerToTasMessage::Reload).unwrap();
+                }
+
                 let frame_by_frame_button_enabled = self.playback_state != PlaybackState::Stopped;
                 let frame_button = if self.playback_state == PlaybackState::Paused {
                     ui.add_enabled(
@@ -253,16 +468,21 @@ impl TasInterface {
         });
 
         ui.horizontal(|ui| {
-            let skip_label = ui.label("Skip to tick: ");
+            let skip_label = ui.label("Skip to: ");
+            let skipto_label = ui.add(label_combobox(
+                "skipto_label",
+                &mut self.skipto_label,
+                &self.labels,
+            ));
             let skipto = ui
-                .add(scrollable_dragvalue(&mut self.skipto))
+                .add_enabled(
+                    self.skipto_label.is_none(),
+                    scrollable_dragvalue(&mut self.skipto),
+                )
                 .labelled_by(skip_label.id);
 
-            if skipto.changed() {
-                match self
-                    .to_server
-                    .send(ControllerToTasMessage::SkipTo(self.skipto))
-                {
+            if skipto.changed() || skipto_label.changed() {
+                match self.to_server.send(self.skipto_message()) {
                     Ok(_) => {}
                     Err(err) => println!("{err}"),
                 }
@@ -270,23 +490,25 @@ impl TasInterface {
         });
 
         ui.horizontal(|ui| {
-            let pauseat_label = ui.label("Pause at tick: ");
+            let pauseat_label = ui.label("Pause at: ");
+            let pauseat_combobox = ui.add_enabled(
+                !self.always_pause_after_skip,
+                label_combobox("pauseat_label", &mut self.pauseat_label, &self.labels),
+            );
             let pauseat = ui
                 .add_enabled(
-                    !self.always_pause_after_skip,
+                    !self.always_pause_after_skip && self.pauseat_label.is_none(),
                     scrollable_dragvalue(&mut self.pauseat),
                 )
                 .labelled_by(pauseat_label.id);
 
             if self.always_pause_after_skip {
                 self.pauseat = self.skipto + 1;
+                self.pauseat_label = self.skipto_label.clone();
             }
 
-            if pauseat.changed() || self.always_pause_after_skip {
-                match self
-                    .to_server
-                    .send(ControllerToTasMessage::PauseAt(self.pauseat))
-                {
+            if pauseat.changed() || pauseat_combobox.changed() || self.always_pause_after_skip {
+                match self.to_server.send(self.pauseat_message()) {
                     Ok(_) => {}
                     Err(err) => println!("{err}"),
                 }
@@ -295,6 +517,104 @@ impl TasInterface {
 
         ui.checkbox(&mut self.always_pause_after_skip, "Pause after skip");
 
+        if ui
+            .checkbox(
+                &mut self.pause_on_assertion_failure,
+                "Pause on failed assertion",
+            )
+            .changed()
+        {
+            self.to_server
+                .send(ControllerToTasMessage::PauseOnAssertionFailure(
+                    self.pause_on_assertion_failure,
+                ))
+                .unwrap();
+        }
+
+        if ui
+            .checkbox(&mut self.legit_mode, "Legit mode")
+            .on_hover_text(
+                "Refuse the scripts that use setpos, setang or lookat, \
+                and disable the noclip and position restore hotkeys",
+            )
+            .changed()
+        {
+            self.to_server
+                .send(ControllerToTasMessage::LegitMode(self.legit_mode))
+                .unwrap();
+        }
+
+        ui.horizontal(|ui| {
+            let label = ui.label("Record to:");
+            ui.add_enabled(
+                !self.recording,
+                egui::TextEdit::singleline(&mut self.record_filename),
+            )
+            .labelled_by(label.id);
+
+            let text = if self.recording { "Stop recording" } else { "Record" };
+            if ui
+                .button(text)
+                .on_hover_text("Write the inputs of the player to a script")
+                .clicked()
+            {
+                self.recording = !self.recording;
+                let msg = if self.recording {
+                    ControllerToTasMessage::StartRecording(self.record_filename.clone())
+                } else {
+                    ControllerToTasMessage::StopRecording
+                };
+                self.to_server.send(msg).unwrap();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let can_take_over = self.playback_state == PlaybackState::Paused && !self.recording;
+            if ui
+                .add_enabled(can_take_over, egui::Button::new("Take over"))
+                .on_hover_text(
+                    "Play from the current tick yourself, and record your inputs \
+                    to replace the rest of the script",
+                )
+                .clicked()
+            {
+                self.recording = true;
+                self.takeover_error = None;
+                self.to_server
+                    .send(ControllerToTasMessage::TakeOver {
+                        write_to_script: self.append_to_script,
+                    })
+                    .unwrap();
+            }
+            ui.checkbox(&mut self.append_to_script, "Write to script");
+        });
+
+        ui.horizontal(|ui| {
+            let label = ui.label("Savestate slot:");
+            ui.add(egui::DragValue::new(&mut self.savestate_slot))
+                .labelled_by(label.id);
+
+            if ui
+                .button("Save")
+                .on_hover_text("Save the player and the playback on the current tick")
+                .clicked()
+            {
+                self.to_server
+                    .send(ControllerToTasMessage::SaveState(self.savestate_slot))
+                    .unwrap();
+            }
+            if ui
+                .add_enabled(!self.legit_mode, egui::Button::new("Load"))
+                .on_hover_text("Go back to the saved tick and pause there, ending the recording")
+                .clicked()
+            {
+                self.recording = false;
+                self.to_server
+                    .send(ControllerToTasMessage::LoadState(self.savestate_slot))
+                    .unwrap();
+            }
+        });
+
         ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
             ui.horizontal(|ui| {
                 let label = ui.label("File:");
@@ -1,3 +1,4 @@
+use common::script::{Label, LintWarning, ScriptError, Severity};
 use egui::Event;
 
 /// Create a DragValue with additionnal scroll interactions
@@ -34,6 +35,89 @@ pub fn scrollable_dragvalue(value: &mut u32) -> impl egui::Widget + '_ {
     }
 }
 
+/// Create a ComboBox to choose between a tick and one of the labels of the script.
+/// `None` means the tick is used.
+pub fn label_combobox<'a>(
+    id: &'a str,
+    selected: &'a mut Option<String>,
+    labels: &'a [Label],
+) -> impl egui::Widget + 'a {
+    move |ui: &mut egui::Ui| {
+        let previous = selected.clone();
+
+        let mut response = egui::ComboBox::from_id_source(id)
+            .selected_text(selected.as_deref().unwrap_or("Tick"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(selected, None, "Tick");
+                for label in labels {
+                    ui.selectable_value(
+                        selected,
+                        Some(label.name.clone()),
+                        format!("{} (tick {})", label.name, label.tick),
+                    );
+                }
+            })
+            .response;
+
+        if *selected != previous {
+            response.mark_changed();
+        }
+
+        response
+    }
+}
+
+/// Display a script error, with its location highlighted.
+/// Hovering shows the expected tokens, the hint and the context of the error.
+pub fn script_error(error: &ScriptError) -> impl egui::Widget + '_ {
+    move |ui: &mut egui::Ui| {
+        let location = match &error.file {
+            Some(file) => format!("{file}:{}:{}", error.line, error.column),
+            None => format!("line {}:{}", error.line, error.column),
+        };
+
+        let mut details = vec![];
+        if !error.expected.is_empty() {
+            details.push(format!("Expected {}", error.expected.join(" or ")));
+        }
+        if let Some(found) = &error.found {
+            details.push(format!("Found {found}"));
+        }
+        if let Some(hint) = &error.hint {
+            details.push(format!("Hint: {hint}"));
+        }
+        details.extend(error.context.iter().cloned());
+
+        let response = ui
+            .horizontal_wrapped(|ui| {
+                let color = match error.severity {
+                    Severity::Error => ui.visuals().error_fg_color,
+                    Severity::Warning => ui.visuals().warn_fg_color,
+                };
+                ui.colored_label(color, location);
+                ui.label(&error.message);
+            })
+            .response;
+
+        if details.is_empty() {
+            response
+        } else {
+            response.on_hover_text(details.join("\n"))
+        }
+    }
+}
+
+/// Display a warning of the linter, with its category
+pub fn lint_warning(lint: &LintWarning) -> impl egui::Widget + '_ {
+    move |ui: &mut egui::Ui| {
+        ui.horizontal(|ui| {
+            ui.weak(format!("[{}]", lint.category));
+            ui.add(script_error(&lint.warning));
+        })
+        .response
+    }
+}
+
 // pub fn file_combobox(value: &mut String, folder: String) -> impl egui::Widget + '_ {
 //     let options = vec![
 //         "7lasersv2.wtas".to_string(),
@@ -1,3 +1,4 @@
+use common::script::{GameSettings, Input, MouseButton};
 use common::tas::PlaybackState;
 use rand::Rng;
 use retour::static_detour;
@@ -6,10 +7,13 @@ use std::sync::{LazyLock, Mutex};
 use std::time;
 use std::{ffi::CStr, marker::PhantomData};
 use tracing::{debug, error, info};
-use windows::Win32::UI::WindowsAndMessaging::MSG;
-use windows::Win32::{Foundation::POINT, UI::Input::RAWINPUT};
+use windows::Win32::UI::WindowsAndMessaging::{GetClientRect, MSG};
+use windows::Win32::{
+    Foundation::{HWND, POINT, RECT},
+    UI::Input::RAWINPUT,
+};
 
-use crate::tas_player::TAS_PLAYER;
+use crate::tas_player::{TasPlayer, TAS_PLAYER};
 use crate::witness::windows_types::{Message, VirtualKeyCode};
 use crate::witness::witness_types::{Color, Entity, Vec2, Vec3};
 
@@ -120,6 +124,23 @@ impl<'a, T: Copy> PointerChain<'a, T> {
 
         *(addr as *mut T) = value;
     }
+
+    /// Whether the pointers of the chain are all set, as they are once what
+    /// they lead to is loaded
+    ///
+    /// # Safety
+    /// This function is unsafe because it manipulates raw pointers
+    pub unsafe fn is_set(&self) -> bool {
+        let mut addr = self.0[0];
+        for offset in &self.0[1..] {
+            addr = *(addr as *const usize);
+            if addr == 0 {
+                return false;
+            }
+            addr += offset;
+        }
+        true
+    }
 }
 
 // Hooking shit, split because recursion limit
@@ -182,6 +203,9 @@ static mut SAVED_CAM_ANG: Option<Vec2> = None;
 // Holds the real tabbed out value, since we make the game forget
 static mut TABBED_OUT: bool = false;
 
+/// The window of the game, as seen by its window procedure
+static mut GAME_WINDOW: usize = 0;
+
 // Variables for save loading
 pub static mut SAVE_PATH: PointerChain<*mut i8> = PointerChain::new(&[0x14062d790]);
 pub static LOAD_SAVE_FLAG: PointerChain<bool> = PointerChain::new(&[0x14062d789]);
@@ -189,6 +213,30 @@ pub static mut APPDATA_PATH: PointerChain<*mut i8> = PointerChain::new(&[0x14062
 
 pub static mut RNG_SEED: PointerChain<*mut u32> = PointerChain::new(&[0x14062d0b0]);
 
+/// Returns the settings of the game that the scripts can depend on.
+/// The resolution is the size of the inside of the game window.
+/// We don't know where the fov, sensitivity and version are stored yet.
+pub fn game_settings() -> GameSettings {
+    let mut rect = RECT::default();
+    let resolution = match unsafe { GAME_WINDOW } {
+        // No message was sent to the window yet
+        0 => None,
+        window => unsafe { GetClientRect(HWND(window as isize), &mut rect) }
+            .ok()
+            .map(|()| {
+                (
+                    (rect.right - rect.left) as u32,
+                    (rect.bottom - rect.top) as u32,
+                )
+            }),
+    };
+
+    GameSettings {
+        resolution,
+        ..Default::default()
+    }
+}
+
 // ------------------------------------------------------------------------------------
 //                                 OUR OVERRIDES
 // ------------------------------------------------------------------------------------
@@ -220,34 +268,13 @@ fn execute_tas_inputs() {
     match unsafe { (player.as_mut(), HANDLE_MSG_PARAM1) } {
         (Some(tas_player), Some(handle_message_this)) => unsafe {
             if let Some(controller) = tas_player.get_controller() {
-                // Movement
-                match (controller.current.forward, controller.previous.forward) {
-                    (true, false) => press_down!(VirtualKeyCode::W),
-                    (false, true) => press_up!(VirtualKeyCode::W),
-                    _ => 0,
-                };
-                match (controller.current.backward, controller.previous.backward) {
-                    (true, false) => press_down!(VirtualKeyCode::S),
-                    (false, true) => press_up!(VirtualKeyCode::S),
-                    _ => 0,
-                };
-                match (controller.current.left, controller.previous.left) {
-                    (true, false) => press_down!(VirtualKeyCode::A),
-                    (false, true) => press_up!(VirtualKeyCode::A),
-                    _ => 0,
-                };
-                match (controller.current.right, controller.previous.right) {
-                    (true, false) => press_down!(VirtualKeyCode::D),
-                    (false, true) => press_up!(VirtualKeyCode::D),
-                    _ => 0,
-                };
-
-                // Running
-                match (controller.current.running, controller.previous.running) {
-                    (true, false) => press_down!(VirtualKeyCode::LShift),
-                    (false, true) => press_up!(VirtualKeyCode::LShift),
-                    _ => 0,
-                };
+                // Keyboard
+                for code in controller.current.keys.difference(&controller.previous.keys) {
+                    press_down!(code);
+                }
+                for code in controller.previous.keys.difference(&controller.current.keys) {
+                    press_up!(code);
+                }
 
                 // Puzzle mode toggle
                 let msg_template = MSG {
@@ -321,6 +348,20 @@ fn handle_all_messages(this: usize, idk: u64) {
 fn handle_message(this: usize, message: *const MSG) -> u64 {
     let val = unsafe { *message }.message;
 
+    // The clicks sent by the TAS do not go through this hook
+    let click = match Message::try_from(val) {
+        Ok(Message::WM_LBUTTONDOWN) => Some((MouseButton::Left, true)),
+        Ok(Message::WM_LBUTTONUP) => Some((MouseButton::Left, false)),
+        Ok(Message::WM_RBUTTONDOWN) => Some((MouseButton::Right, true)),
+        Ok(Message::WM_RBUTTONUP) => Some((MouseButton::Right, false)),
+        _ => None,
+    };
+    if let Some((button, down)) = click {
+        if let Some(tas_player) = TAS_PLAYER.lock().unwrap().as_mut() {
+            tas_player.record_input(Input::Mouse(button), down);
+        }
+    }
+
     match Message::try_from(val) {
         Ok(msg) => match msg {
             Message::WM_INPUT => {}
@@ -355,6 +396,18 @@ fn declare_console_command(this: usize, func: usize, name: *mut i8, arg_type: u3
     unsafe { DeclareConsoleCommand.call(this, func, name, arg_type, arg_count) }
 }
 
+/// The keys the tool reacts to, which are left out of the recordings of the inputs.
+/// ESC stops the playback, P starts it, N toggles noclip, J and K save and restore
+/// the player position, and E toggles the puzzle debug.
+pub const HOTKEYS: [VirtualKeyCode; 6] = [
+    VirtualKeyCode::ESC,
+    VirtualKeyCode::P,
+    VirtualKeyCode::N,
+    VirtualKeyCode::J,
+    VirtualKeyCode::K,
+    VirtualKeyCode::E,
+];
+
 fn handle_keyboard_input(
     this: usize,
     ri_key_break: u8,
@@ -363,6 +416,12 @@ fn handle_keyboard_input(
     virtual_keycode: u32,
     scan_code: u32,
 ) -> u64 {
+    if let Ok(key) = VirtualKeyCode::try_from(virtual_keycode) {
+        if let Some(tas_player) = TAS_PLAYER.lock().unwrap().as_mut() {
+            tas_player.record_input(Input::Keyboard(key), press_down == 1);
+        }
+    }
+
     if virtual_keycode == VirtualKeyCode::ESC as u32 && press_down == 1 {
         if let Some(tas_player) = TAS_PLAYER.lock().unwrap().as_mut() {
             tas_player.stop();
@@ -375,18 +434,25 @@ fn handle_keyboard_input(
         }
     }
 
-    if virtual_keycode == VirtualKeyCode::N as u32 && press_down == 1 {
+    // Noclip and moving the player are not legitimate
+    let legit = TAS_PLAYER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(TasPlayer::is_legit);
+
+    if virtual_keycode == VirtualKeyCode::N as u32 && press_down == 1 && !legit {
         unsafe { NOCLIP.write(!NOCLIP.read()) };
     }
 
-    if virtual_keycode == VirtualKeyCode::J as u32 && press_down == 1 {
+    if virtual_keycode == VirtualKeyCode::J as u32 && press_down == 1 && !legit {
         unsafe {
             SAVED_CAM_POS = Some(PLAYER_POS.read());
             SAVED_CAM_ANG = Some(PLAYER_ANG.read());
         };
         info!("Saved camera pos")
     }
-    if virtual_keycode == VirtualKeyCode::K as u32 && press_down == 1 {
+    if virtual_keycode == VirtualKeyCode::K as u32 && press_down == 1 && !legit {
         unsafe {
             if let (Some(pos), Some(ang)) = (SAVED_CAM_POS, SAVED_CAM_ANG) {
                 PLAYER_POS.write(pos);
@@ -447,7 +513,10 @@ fn get_mouse_delta_pos(
     };
 
     let controller = match player.as_mut() {
-        Some(tas_player) => tas_player.get_controller(),
+        Some(tas_player) => {
+            tas_player.record_mouse(unsafe { (*mouse_x_out, *mouse_y_out) });
+            tas_player.get_controller()
+        }
         None => return,
     };
 
@@ -554,6 +623,8 @@ fn middle_of_drawing(param1: usize, param2: usize) {
 }
 
 fn window_proc_callback(idc: usize, msg: u32, wparam: u64, lparam: u64) -> usize {
+    unsafe { GAME_WINDOW = idc };
+
     // Override the message indicating lost focus, make the game think it is focused always
     match msg {
         0x86 => unsafe {
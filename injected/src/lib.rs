@@ -8,6 +8,7 @@ use common::witness;
 use tracing::{error, info};
 
 pub mod hooks;
+pub mod savestate;
 pub mod tas_player;
 
 
@@ -0,0 +1,72 @@
+use std::ptr::addr_of;
+
+use common::playback::PlaybackSnapshot;
+
+use crate::hooks::{INTERACTION_STATUS, NOCLIP, PLAYER, PLAYER_ANG, RNG_SEED, VERTICAL_SMOOTHING};
+use crate::tas_player::Playertrace;
+use crate::witness::witness_types::{Entity, Vec2};
+
+/// The state of the game we know where to find.
+/// The rest of it is listed in `NOT_CAPTURED`.
+#[derive(Clone, Copy)]
+pub struct GameState {
+    /// The known part of the player entity, with its position
+    player: Entity,
+    player_ang: Vec2,
+    interaction_status: u32,
+    vertical_smoothing: f32,
+    noclip: bool,
+    rng_seed: u32,
+}
+
+impl GameState {
+    /// The state of the game that savestates do not capture yet
+    pub const NOT_CAPTURED: &'static [&'static str] = &[
+        "puzzle panels, doors and the other entities",
+        "the fields of the player entity past its position",
+        "the player's velocity",
+        "animations, cinematics and audio",
+        "the game's own save file",
+    ];
+
+    /// Whether the player entity is loaded, which it is not in the menus and loading screens
+    pub fn is_loaded() -> bool {
+        unsafe { PLAYER.is_set() }
+    }
+
+    /// Reads the state from the game
+    ///
+    /// # Safety
+    /// The player entity must be loaded, as its pointer chain is followed
+    pub unsafe fn capture() -> Self {
+        Self {
+            player: PLAYER.read(),
+            player_ang: PLAYER_ANG.read(),
+            interaction_status: INTERACTION_STATUS.read(),
+            vertical_smoothing: VERTICAL_SMOOTHING.read(),
+            noclip: NOCLIP.read(),
+            rng_seed: *(*addr_of!(RNG_SEED)).read(),
+        }
+    }
+
+    /// Writes the state back to the game
+    ///
+    /// # Safety
+    /// The player entity must be loaded, as its pointer chain is followed
+    pub unsafe fn restore(&self) {
+        PLAYER.write(self.player);
+        PLAYER_ANG.write(self.player_ang);
+        INTERACTION_STATUS.write(self.interaction_status);
+        VERTICAL_SMOOTHING.write(self.vertical_smoothing);
+        NOCLIP.write(self.noclip);
+        *(*addr_of!(RNG_SEED)).read() = self.rng_seed;
+    }
+}
+
+/// The game and the playback on a tick, to go back to
+#[derive(Clone)]
+pub struct Savestate {
+    pub game: GameState,
+    pub playback: PlaybackSnapshot,
+    pub trace: Playertrace,
+}
@@ -1,58 +1,86 @@
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::ops::Range;
+use std::path::Path;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Mutex;
 
 use crate::communication::{server_thread, ControllerToTasMessage, TasToControllerMessage};
 use crate::hooks::{
-    CopyString, APPDATA_PATH, INTERACTION_STATUS, PLAYER_ANG, PLAYER_POS, RNG_SEED, SAVE_PATH,
+    game_settings, CopyString, APPDATA_PATH, HOTKEYS, INTERACTION_STATUS, NOCLIP, PLAYER_ANG, PLAYER_POS, RNG_SEED, SAVE_PATH,
 };
+use crate::savestate::{GameState, Savestate};
 use crate::witness::witness_types::{InteractionStatus, Vec2};
 use crate::{
     hooks::{DoRestart, LOAD_SAVE_FLAG, MAIN_LOOP_COUNT, NEW_GAME_FLAG, PLAYER},
-    script::{self, Script, StartType},
+    script::{Script, Severity, StartType},
     witness::witness_types::Vec3,
 };
+use common::playback::{ControllerState, GameInterface, Playback, Takeover};
+use common::recording::Recorder;
+use common::script::{Input, PrintOptions};
 use common::tas::{PlaybackState, TraceDrawOptions, TraceInterval};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 pub static TAS_PLAYER: Mutex<Option<TasPlayer>> = Mutex::new(None);
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
-pub struct HalfControllerState {
-    pub forward: bool,
-    pub backward: bool,
-    pub left: bool,
-    pub right: bool,
-    pub running: bool,
-
-    pub mouse_pos: (i32, i32),
-    pub left_click: bool,
-    pub right_click: bool,
+/// The running game, as seen by the playback
+struct Game;
+
+impl GameInterface for Game {
+    fn player_pos(&self) -> Vec3 {
+        unsafe { PLAYER_POS.read() }
+    }
+
+    fn set_player_pos(&mut self, pos: Vec3) {
+        unsafe { PLAYER_POS.write(pos) }
+    }
+
+    fn player_ang(&self) -> Vec2 {
+        unsafe { PLAYER_ANG.read() }
+    }
+
+    fn set_player_ang(&mut self, ang: Vec2) {
+        unsafe { PLAYER_ANG.write(ang) }
+    }
+
+    fn interaction_status(&self) -> Option<InteractionStatus> {
+        unsafe { INTERACTION_STATUS.read() }.try_into().ok()
+    }
+
+    fn reset_rng(&mut self) {
+        unsafe {
+            let rng_ptr = RNG_SEED.read();
+            *rng_ptr = 0xbeefface;
+        }
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct ControllerState {
-    pub current: HalfControllerState,
-    pub previous: HalfControllerState,
+/// What to do with the recorded inputs
+enum RecordingOutput {
+    /// Write them as a new script to the file
+    File(String),
+    /// Send them to the controller, to append to the script that was taken over
+    Takeover {
+        takeover: Takeover,
+        write_to_script: bool,
+    },
 }
 
 pub struct TasPlayer {
     send: Sender<TasToControllerMessage>,
     recv: Receiver<ControllerToTasMessage>,
-    state: PlaybackState,
 
+    /// Plays the script, the ticks it counts start at `start_tick`
+    playback: Playback,
     start_tick: u32,
-    current_tick: u32,
-    skipto_tick: u32,
-    pauseat_tick: u32,
-
-    next_line: usize,
     script_name: String,
-    script: Option<script::Script>,
 
-    controller: ControllerState,
+    /// The inputs of the player being recorded, with where they go
+    recording: Option<(Recorder, RecordingOutput)>,
+    record_start: u32,
+
+    savestates: HashMap<u32, Savestate>,
 
     // Utilities
     pub trace: Playertrace,
@@ -75,17 +103,14 @@ impl TasPlayer {
         std::thread::spawn(|| server_thread(send, recv));
 
         Self {
+            playback: Playback::new(to_client.clone()),
             send: to_client,
             recv: from_client,
-            state: PlaybackState::Stopped,
             start_tick: 0,
-            current_tick: 0,
-            skipto_tick: 0,
-            pauseat_tick: 0,
-            next_line: 0,
             script_name: "".to_string(),
-            script: None,
-            controller: Default::default(),
+            recording: None,
+            record_start: 0,
+            savestates: HashMap::new(),
             trace: Default::default(),
         }
     }
@@ -99,29 +124,38 @@ impl TasPlayer {
             self.script_name = file;
         }
 
-        self.script = match std::fs::read_to_string("./tas/".to_string() + &self.script_name) {
-            Err(err) => {
-                error!("{err}");
-                self.send
-                    .send(TasToControllerMessage::ParseErrors(vec![err.to_string()]))
-                    .unwrap();
-                None
+        let Some(script) = self.load_script() else { return };
+        self.send
+            .send(TasToControllerMessage::ScriptMetadata(script.metadata.clone()))
+            .unwrap();
+
+        // Refuse to play if the game settings would make the script desync
+        let checks = script.metadata.check(&game_settings());
+        let refused = checks.iter().any(|check| check.severity == Severity::Error);
+        for check in &checks {
+            match check.severity {
+                Severity::Error => error!("{}", check.message),
+                Severity::Warning => warn!("{}", check.message),
             }
-            Ok(src) => match Script::try_from(src) {
-                Err(parse_errs) => {
-                    for err in &parse_errs {
-                        error!("Parse error: {err}");
-                    }
-                    self.send
-                        .send(TasToControllerMessage::ParseErrors(parse_errs))
-                        .unwrap();
-                    None
-                }
-                Ok(script) => Some(script),
-            },
-        };
+        }
+        self.send
+            .send(TasToControllerMessage::SettingChecks(checks))
+            .unwrap();
+
+        if refused {
+            error!("The game settings do not match the script, not starting.");
+            return;
+        }
 
-        let Some(script) = &self.script else { return };
+        // Legit runs cannot start with noclip on
+        if self.playback.legit_mode || script.metadata.legit {
+            unsafe {
+                if NOCLIP.read() {
+                    warn!("Turning noclip off for a legit run");
+                    NOCLIP.write(false);
+                }
+            }
+        }
 
         match &script.start {
             StartType::Now => {}
@@ -158,25 +192,84 @@ impl TasPlayer {
             },
         }
 
-        self.controller = Default::default();
         self.start_tick = unsafe { MAIN_LOOP_COUNT.read() };
-        self.current_tick = 0;
-        self.next_line = 0;
-        self.state = PlaybackState::Playing;
+        self.playback.start(script);
 
         self.trace.clear();
 
         info!("Started TAS")
     }
 
+    /// Parses the script file, and sends its errors, or its warnings and labels
+    fn load_script(&self) -> Option<Script> {
+        // Included files are looked up relative to the script
+        let script = match Script::from_file(&Path::new("./tas").join(&self.script_name)) {
+            Err(parse_errs) => {
+                for err in &parse_errs {
+                    error!("Parse error: {err}");
+                }
+                self.send
+                    .send(TasToControllerMessage::ParseErrors(parse_errs))
+                    .unwrap();
+                return None;
+            }
+            Ok(script) => script,
+        };
+
+        if self.playback.legit_mode {
+            let errors = script.legit_errors();
+            if !errors.is_empty() {
+                for err in &errors {
+                    error!("Legit mode: {err}");
+                }
+                self.send
+                    .send(TasToControllerMessage::ParseErrors(errors))
+                    .unwrap();
+                return None;
+            }
+        }
+
+        let lints = script.lint();
+        for lint in &lints {
+            warn!("Lint: {lint}");
+        }
+        self.send
+            .send(TasToControllerMessage::LintWarnings(lints))
+            .unwrap();
+        self.send
+            .send(TasToControllerMessage::Labels(script.labels.clone()))
+            .unwrap();
+
+        Some(script)
+    }
+
+    /// Reparses the script while it plays, and keeps playing the new version
+    /// if it plays the same as the old one up to the current tick
+    fn reload(&mut self) {
+        if self.playback.get_playback_state() == PlaybackState::Stopped {
+            warn!("No script is playing, nothing to reload");
+            return;
+        }
+
+        let Some(script) = self.load_script() else { return };
+        let diverged_at = self.playback.reload(script).err();
+        match diverged_at {
+            Some(tick) => warn!("The script changed on tick {tick}, keeping the old one"),
+            None => info!("Reloaded {}", self.script_name),
+        }
+        self.send
+            .send(TasToControllerMessage::Reloaded { diverged_at })
+            .unwrap();
+    }
+
     /// Stops the TAS
     pub fn stop(&mut self) {
-        if self.state != PlaybackState::Stopped {
-            self.state = PlaybackState::Stopped;
-    
-            let ticks = self.current_tick;
-            info!("Stopped TAS after {ticks} ticks.")
-        }
+        self.playback.stop()
+    }
+
+    /// Tick of the game, counted from the start of the TAS
+    fn game_tick(&self) -> u32 {
+        unsafe { MAIN_LOOP_COUNT.read() }.saturating_sub(self.start_tick)
     }
 
     /// Get the controller input and possibly advance state.
@@ -199,51 +292,11 @@ impl TasPlayer {
             ))
             .unwrap();
 
-        // If we are not running, exit
-        if self.state == PlaybackState::Stopped {
-            // Release all buttons if not done already
-            if self.controller.current != HalfControllerState::default() {
-                self.controller.previous = self.controller.current;
-                self.controller.current = HalfControllerState::default();
-                return Some(&self.controller)
-            }
-
-            return None;
-        }
-
-        let script = self.script.as_ref()?;
-
-        if self.next_line >= script.lines.len() {
-            self.stop();
-            return None;
-        }
-
-        // Update controller
-        // Get pressed keys
-        let current_tick = unsafe { MAIN_LOOP_COUNT.read() } - self.start_tick;
-        if current_tick == 0 {
-            // Reset RNG
-            unsafe {
-                let rng_ptr = RNG_SEED.read();
-                *rng_ptr = 0xbeefface;
-            }
-        }
-        if self.current_tick != current_tick {
-            self.send
-                .send(TasToControllerMessage::CurrentTick(current_tick))
-                .unwrap();
-
-            if current_tick == self.pauseat_tick {
-                self.state = PlaybackState::Paused;
-
-                self.send
-                    .send(TasToControllerMessage::PlaybackState(
-                        self.get_playback_state(),
-                    ))
-                    .unwrap();
-            }
-
-            // Update the player pos history
+        // Update the player pos history, before the tools of the tick move the player
+        let current_tick = self.game_tick();
+        if self.playback.get_playback_state() != PlaybackState::Stopped
+            && self.playback.get_current_tick() != current_tick
+        {
             unsafe {
                 self.trace.push(
                     PLAYER.read().position,
@@ -251,74 +304,16 @@ impl TasPlayer {
                     INTERACTION_STATUS.read().try_into().unwrap(),
                 )
             };
-
-            self.current_tick = current_tick;
-            let next_line = &script.lines[self.next_line];
-
-            self.controller.previous = self.controller.current;
-
-            // Do the auto lifting of the mouse buttons
-            if self.controller.previous.left_click {
-                self.controller.current.left_click = false;
-            }
-            if self.controller.previous.right_click {
-                self.controller.current.right_click = false;
-            }
-
-            if next_line.tick == current_tick {
-                self.next_line += 1;
-
-                for key in &next_line.keys {
-                    match key {
-                        // Movement
-                        'U' => self.controller.current.forward = true,
-                        'u' => self.controller.current.forward = false,
-                        'L' => self.controller.current.left = true,
-                        'l' => self.controller.current.left = false,
-                        'D' => self.controller.current.backward = true,
-                        'd' => self.controller.current.backward = false,
-                        'R' => self.controller.current.right = true,
-                        'r' => self.controller.current.right = false,
-
-                        // Sprint
-                        'S' => self.controller.current.running = true,
-                        's' => self.controller.current.running = false,
-
-                        // Toggle puzzle
-                        'P' => self.controller.current.left_click = true,
-                        'p' => self.controller.current.right_click = true,
-
-                        _ => {}
-                    }
-                }
-
-                if let Some(mouse) = next_line.mouse {
-                    self.controller.current.mouse_pos = mouse;
-                }
-
-                // Execute the tools
-                if let Some(tools) = &next_line.tools {
-                    for tool in tools {
-                        match tool {
-                            script::Tool::SetPos { pos, ang } => unsafe {
-                                PLAYER_POS.write(*pos);
-                                PLAYER_ANG.write(*ang);
-                            },
-                        }
-                    }
-                }
-            }
         }
 
-        // Return it
-        Some(&self.controller)
+        self.playback.tick(current_tick, &mut Game)
     }
 
     fn update_from_server(&mut self, block: bool) {
         // We're using a loop and not try_iter here because the borrow checker
         // doesn't like it
         loop {
-            let msg = if block && self.state == PlaybackState::Paused {
+            let msg = if block && self.playback.get_playback_state() == PlaybackState::Paused {
                 self.recv.recv().ok()
             } else {
                 self.recv.try_recv().ok()
@@ -331,29 +326,234 @@ impl TasPlayer {
 
             match msg {
                 ControllerToTasMessage::PlayFile(filename) => {
-                    if self.state == PlaybackState::Paused {
-                        self.state = PlaybackState::Playing;
+                    if self.playback.get_playback_state() == PlaybackState::Paused {
+                        self.playback.resume();
                     } else {
                         self.start(Some(filename));
                     }
                 }
                 ControllerToTasMessage::Stop => self.stop(),
-                ControllerToTasMessage::SkipTo(tick) => self.skipto_tick = tick,
-                ControllerToTasMessage::PauseAt(tick) => self.pauseat_tick = tick,
+                ControllerToTasMessage::Reload => self.reload(),
+                ControllerToTasMessage::SkipTo(tick) => self.playback.skip_to(tick),
+                ControllerToTasMessage::PauseAt(tick) => self.playback.pause_at(tick),
+                ControllerToTasMessage::SkipToLabel(label) => self.playback.skip_to_label(label),
+                ControllerToTasMessage::PauseAtLabel(label) => self.playback.pause_at_label(label),
                 ControllerToTasMessage::AdvanceFrame => {
-                    self.state = PlaybackState::Paused;
+                    self.playback.pause();
                     return;
                 }
                 ControllerToTasMessage::TeleportToTick(tick) => {
-                    if self.state == PlaybackState::Stopped {
+                    if self.playback.get_playback_state() == PlaybackState::Stopped {
                         self.trace.teleport_tick(tick);
                     }
                 }
                 ControllerToTasMessage::TraceOptions(opt) => self.trace.draw_option = opt,
+                ControllerToTasMessage::PauseOnAssertionFailure(pause) => {
+                    self.playback.pause_on_assertion_failure = pause
+                }
+                ControllerToTasMessage::LegitMode(legit) => self.playback.legit_mode = legit,
+                ControllerToTasMessage::StartRecording(file) => self.start_recording(file),
+                ControllerToTasMessage::TakeOver { write_to_script } => {
+                    self.take_over(write_to_script)
+                }
+                ControllerToTasMessage::StopRecording => self.stop_recording(),
+                ControllerToTasMessage::SaveState(slot) => self.save_state(slot),
+                ControllerToTasMessage::LoadState(slot) => self.load_state(slot),
             }
         }
     }
 
+    /// Saves the game and the playback on the current tick to `slot`
+    fn save_state(&mut self, slot: u32) {
+        if !GameState::is_loaded() {
+            let err = "Savestates can only be saved once the player is loaded".to_string();
+            error!("{err}");
+            self.send
+                .send(TasToControllerMessage::SavestateError(err))
+                .unwrap();
+            return;
+        }
+
+        let savestate = Savestate {
+            game: unsafe { GameState::capture() },
+            playback: self.playback.snapshot(),
+            trace: self.trace.clone(),
+        };
+        let tick = savestate.playback.tick();
+        self.savestates.insert(slot, savestate);
+
+        info!("Saved slot {slot} on tick {tick}");
+        let not_captured = GameState::NOT_CAPTURED
+            .iter()
+            .map(|state| state.to_string())
+            .collect();
+        self.send
+            .send(TasToControllerMessage::StateSaved {
+                slot,
+                tick,
+                not_captured,
+            })
+            .unwrap();
+    }
+
+    /// Puts the game and the playback back as they were saved in `slot`, paused on its tick.
+    /// This ends the recording, as the recorded inputs would not follow each other.
+    fn load_state(&mut self, slot: u32) {
+        let err = if self.is_legit() {
+            Some("Savestates cannot be loaded in a legit run".to_string())
+        } else if !self.savestates.contains_key(&slot) {
+            Some(format!("Slot {slot} is empty"))
+        } else if !GameState::is_loaded() {
+            Some("Savestates can only be loaded once the player is loaded".to_string())
+        } else {
+            None
+        };
+        if let Some(err) = err {
+            error!("{err}");
+            self.send
+                .send(TasToControllerMessage::SavestateError(err))
+                .unwrap();
+            return;
+        }
+
+        self.stop_recording();
+        let savestate = self.savestates[&slot].clone();
+        let tick = savestate.playback.tick();
+        unsafe { savestate.game.restore() };
+        self.playback.restore(savestate.playback);
+        // The next frame is the tick after the saved one
+        self.start_tick = unsafe { MAIN_LOOP_COUNT.read() }.saturating_sub(tick);
+
+        let draw_option = self.trace.draw_option;
+        self.trace = savestate.trace;
+        self.trace.draw_option = draw_option;
+
+        info!("Loaded slot {slot}, back on tick {tick}");
+        self.send
+            .send(TasToControllerMessage::StateLoaded { slot, tick })
+            .unwrap();
+        self.send
+            .send(TasToControllerMessage::CurrentTick(tick))
+            .unwrap();
+    }
+
+    /// Starts recording the inputs of the player, to write them to `file` when stopped
+    fn start_recording(&mut self, file: String) {
+        self.stop_recording();
+
+        info!("Recording inputs to {file}");
+        self.record_start = unsafe { MAIN_LOOP_COUNT.read() };
+        let recorder = Recorder::new().ignoring(HOTKEYS.map(Input::Keyboard));
+        self.recording = Some((recorder, RecordingOutput::File(file)));
+    }
+
+    /// Hands the paused game to the player, and records their inputs from the current tick
+    fn take_over(&mut self, write_to_script: bool) {
+        self.stop_recording();
+
+        let takeover = match self.playback.take_over() {
+            Ok(takeover) => takeover,
+            Err(err) => {
+                error!("Cannot take over: {err}");
+                self.send
+                    .send(TasToControllerMessage::TakeoverError(err))
+                    .unwrap();
+                return;
+            }
+        };
+
+        info!("Taking over on tick {}", self.playback.get_current_tick());
+        self.record_start = unsafe { MAIN_LOOP_COUNT.read() };
+        let recorder =
+            Recorder::after(&takeover.controller).ignoring(HOTKEYS.map(Input::Keyboard));
+        let output = RecordingOutput::Takeover {
+            takeover,
+            write_to_script,
+        };
+        self.recording = Some((recorder, output));
+    }
+
+    /// Stops the recording, and writes what was recorded to its file
+    fn stop_recording(&mut self) {
+        let Some((recorder, output)) = self.recording.take() else { return };
+        let lines = recorder.finish(self.record_tick());
+
+        let (takeover, write_to_script) = match output {
+            RecordingOutput::File(file) => {
+                let text = Script::from_lines(&lines).print(&PrintOptions::default());
+                match std::fs::write(Path::new("./tas").join(&file), text) {
+                    Ok(()) => info!("Recorded {} lines to {file}", lines.len()),
+                    Err(err) => error!("Cannot write the recording to {file}: {err}"),
+                }
+                return;
+            }
+            RecordingOutput::Takeover {
+                takeover,
+                write_to_script,
+            } => (takeover, write_to_script),
+        };
+
+        let text = takeover.text(lines);
+        self.send
+            .send(TasToControllerMessage::RecordedLines(text.clone()))
+            .unwrap();
+        if !write_to_script {
+            return;
+        }
+
+        // The script was parsed when it started, it must not have changed since.
+        // Its previous version is kept next to it, as the lines after the cut are gone.
+        let path = Path::new("./tas").join(&self.script_name);
+        let mut backup = path.clone().into_os_string();
+        backup.push(".bak");
+        let played = self.playback.script().map(|script| script.src.as_str());
+        let result = match std::fs::read_to_string(&path) {
+            Ok(src) if Some(src.as_str()) == played => std::fs::write(&backup, &src)
+                .and_then(|()| std::fs::write(&path, takeover.append(&src, &text)))
+                .map_err(|err| err.to_string()),
+            Ok(_) => Err("it changed since it was played".to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match result {
+            Ok(()) => info!(
+                "Appended the recording to {}, the previous version is in {}.bak",
+                self.script_name, self.script_name
+            ),
+            Err(err) => {
+                let err = format!("Cannot append the recording to {}: {err}", self.script_name);
+                error!("{err}");
+                self.send
+                    .send(TasToControllerMessage::TakeoverError(err))
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Tick of the game, counted from the start of the recording
+    fn record_tick(&self) -> u32 {
+        unsafe { MAIN_LOOP_COUNT.read() }.saturating_sub(self.record_start)
+    }
+
+    /// Records an input of the player going down or up, if recording
+    pub fn record_input(&mut self, input: Input, down: bool) {
+        let tick = self.record_tick();
+        if let Some((recorder, _)) = &mut self.recording {
+            if down {
+                recorder.press(tick, input);
+            } else {
+                recorder.release(tick, input);
+            }
+        }
+    }
+
+    /// Records a mouse movement of the player, if recording
+    pub fn record_mouse(&mut self, delta: (i32, i32)) {
+        let tick = self.record_tick();
+        if let Some((recorder, _)) = &mut self.recording {
+            recorder.mouse(tick, delta);
+        }
+    }
+
     pub fn block_until_next_frame(&mut self) {
         self.update_from_server(true)
     }
@@ -364,40 +564,37 @@ impl TasPlayer {
             .add_puzzle_click(current_tick, cam_pos, click_dir)
     }
 
-    pub fn send_puzzle_unlock(&self) {
-        let current_tick = unsafe { MAIN_LOOP_COUNT.read() } - self.start_tick;
-        self.send
-            .send(TasToControllerMessage::PuzzleUnlock(current_tick))
-            .unwrap();
+    pub fn send_puzzle_unlock(&mut self) {
+        let current_tick = self.game_tick();
+        self.playback.puzzle_unlocked(current_tick);
     }
 
     pub fn should_do_skipping(&self) -> bool {
-        // Only skip after 60 frames, the "eyes opening" animation fucks things up
-        self.state == PlaybackState::Playing
-            && self.current_tick < self.skipto_tick
-            && self.current_tick > 60
+        self.playback.should_do_skipping()
     }
 
     pub fn get_playback_state(&self) -> PlaybackState {
-        if self.state == PlaybackState::Playing && self.current_tick < self.skipto_tick {
-            PlaybackState::Skipping
-        } else {
-            self.state
-        }
+        self.playback.get_playback_state()
+    }
+
+    /// Whether the tools and hotkeys that are not legitimate are disabled
+    pub fn is_legit(&self) -> bool {
+        self.playback.is_legit()
     }
 
     pub fn get_current_tick(&self) -> u32 {
-        self.current_tick
+        self.playback.get_current_tick()
     }
 }
 
+#[derive(Clone)]
 pub struct TraceTick {
     pub pos: Vec3,
     pub ang: Vec2,
     pub interact: InteractionStatus,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Playertrace {
     pub draw_option: TraceDrawOptions,
     ticks: Vec<TraceTick>,
@@ -0,0 +1,88 @@
+use lsp_types::Position;
+
+// The scripts count offsets in characters, while the editors count
+// columns in UTF-16 code units. These convert between the two.
+
+/// Position of the character at `offset` characters into `text`
+pub fn char_position(text: &str, offset: usize) -> Position {
+    let mut position = Position::new(0, 0);
+    for c in text.chars().take(offset) {
+        if c == '\n' {
+            position.line += 1;
+            position.character = 0;
+        } else {
+            position.character += c.len_utf16() as u32;
+        }
+    }
+
+    position
+}
+
+/// Position of the byte at `offset` into `text`
+pub fn byte_position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    char_position(text, text[..offset].chars().count())
+}
+
+/// Offset in characters of `position` in `text`.
+/// Positions past the end of their line are moved to its end.
+pub fn char_offset(text: &str, position: Position) -> usize {
+    let mut current = Position::new(0, 0);
+    for (offset, c) in text.chars().enumerate() {
+        if current.line == position.line && (current.character >= position.character || c == '\n') {
+            return offset;
+        }
+
+        if c == '\n' {
+            current.line += 1;
+            current.character = 0;
+        } else {
+            current.character += c.len_utf16() as u32;
+        }
+    }
+
+    text.chars().count()
+}
+
+/// The name under the character at `offset`, if any
+pub fn word_at(text: &str, offset: usize) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+
+    let start = chars[..offset.min(chars.len())]
+        .iter()
+        .rposition(|c| !is_word(c))
+        .map_or(0, |index| index + 1);
+    let end = chars[start..]
+        .iter()
+        .position(|c| !is_word(c))
+        .map_or(chars.len(), |len| start + len);
+
+    (start < end).then(|| chars[start..end].iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+
+    use super::{byte_position, char_offset, char_position, word_at};
+
+    #[test]
+    fn test_positions() {
+        let text = "// é😀\n10>U|dx 0\n";
+
+        assert_eq!(char_position(text, 5), Position::new(0, 6));
+        assert_eq!(char_position(text, 9), Position::new(1, 3));
+        // The emoji is 4 bytes long, and 2 UTF-16 code units
+        assert_eq!(byte_position(text, 10), Position::new(1, 0));
+
+        assert_eq!(char_offset(text, Position::new(1, 3)), 9);
+        assert_eq!(char_offset(text, Position::new(0, 100)), 5);
+        assert_eq!(char_offset(text, Position::new(5, 0)), text.chars().count());
+
+        assert_eq!(word_at(text, 12).as_deref(), Some("dx"));
+        assert_eq!(word_at(text, 13).as_deref(), Some("dx"));
+        assert_eq!(word_at(text, 10).as_deref(), Some("U"));
+        assert_eq!(word_at(text, 1), None);
+    }
+}
@@ -0,0 +1,24 @@
+use std::error::Error;
+
+use lsp_server::Connection;
+use tracing::info;
+
+mod document;
+mod server;
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    // Stdout is used to talk with the editor, so the logs go to stderr
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(false)
+        .init();
+
+    info!("Starting the language server");
+
+    let (connection, io_threads) = Connection::stdio();
+    server::run(connection)?;
+    io_threads.join()?;
+
+    info!("Language server stopped");
+    Ok(())
+}
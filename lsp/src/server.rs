@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use common::script::{Script, ScriptError, ScriptLine, Severity, Statement};
+use common::tas::to_time;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Formatting, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, TextEdit, Url,
+};
+use tracing::{debug, warn};
+
+use crate::document::{byte_position, char_offset, char_position, word_at};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+/// Most occurrences of a line listed when hovering it
+const MAX_HOVER_OCCURRENCES: usize = 10;
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// Answers the editor until it asks the server to shut down
+pub fn run(connection: Connection) -> Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server {
+        connection: &connection,
+        documents: HashMap::new(),
+    };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(response.into())?;
+            }
+            Message::Notification(notification) => server.handle_notification(notification)?,
+            Message::Response(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    /// The text of the documents opened in the editor
+    documents: HashMap<Url, String>,
+}
+
+/// Calls `handler` with the parameters of `request`, and wraps its result in a response
+fn respond<R: lsp_types::request::Request>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(err) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string()),
+    }
+}
+
+/// The parameters of `notification`. Invalid ones are logged, and the notification dropped.
+fn notification_params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    match serde_json::from_value(notification.params) {
+        Ok(params) => Some(params),
+        Err(err) => {
+            warn!("Invalid parameters for `{}`: {err}", notification.method);
+            None
+        }
+    }
+}
+
+/// Parses a document. Documents that are not files, like new unsaved ones,
+/// look for the included files in the current directory.
+fn parse(uri: &Url, text: &str) -> std::result::Result<Script, Vec<ScriptError>> {
+    match uri.to_file_path() {
+        Ok(path) => Script::from_source(text.to_string(), &path),
+        Err(_) => Script::try_from(text.to_string()),
+    }
+}
+
+impl Server<'_> {
+    fn handle_request(&self, request: Request) -> Response {
+        debug!("Received request {}", request.method);
+
+        let method = request.method.clone();
+        match method.as_str() {
+            HoverRequest::METHOD => respond::<HoverRequest>(request, |params| self.hover(params)),
+            GotoDefinition::METHOD => {
+                respond::<GotoDefinition>(request, |params| self.definition(params))
+            }
+            Formatting::METHOD => respond::<Formatting>(request, |params| self.format(params)),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request `{method}`"),
+            ),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        debug!("Received notification {}", notification.method);
+
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidOpenTextDocument>(notification) else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish_diagnostics(&uri)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidChangeTextDocument>(notification)
+                else {
+                    return Ok(());
+                };
+                // With full synchronization, the last change holds the whole text
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), change.text);
+                self.publish_diagnostics(&uri)?;
+            }
+            DidSaveTextDocument::METHOD => {
+                // The saved file may be included by the other documents
+                let uris: Vec<Url> = self.documents.keys().cloned().collect();
+                for uri in uris {
+                    self.publish_diagnostics(&uri)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidCloseTextDocument>(notification) else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.send_diagnostics(uri, vec![])?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn publish_diagnostics(&self, uri: &Url) -> Result<()> {
+        let Some(text) = self.documents.get(uri) else {
+            return Ok(());
+        };
+
+        let path = uri.to_file_path().ok();
+        let diagnostics = match parse(uri, text) {
+            Ok(script) => script
+                .lint()
+                .iter()
+                .map(|lint| Diagnostic {
+                    code: Some(NumberOrString::String(lint.category.to_string())),
+                    ..diagnostic(&lint.warning, text, path.as_deref())
+                })
+                .collect(),
+            Err(errors) => errors
+                .iter()
+                .map(|error| diagnostic(error, text, path.as_deref()))
+                .collect(),
+        };
+
+        self.send_diagnostics(uri.clone(), diagnostics)
+    }
+
+    fn send_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into())?;
+
+        Ok(())
+    }
+
+    /// Shows the tick at which the line under the cursor is played
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let text = self.documents.get(uri)?;
+        let script = parse(uri, text).ok()?;
+
+        let offset = char_offset(text, position.position);
+        let path = uri.to_file_path().ok();
+        let occurrences: Vec<(usize, &ScriptLine)> = script
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.generated && line.file == path && line.span.contains(&offset))
+            .collect();
+        let (first_index, first) = *occurrences.first()?;
+
+        let tick = |line: &ScriptLine| {
+            format!(
+                "tick {} ({})",
+                line.absolute_tick,
+                to_time(line.absolute_tick)
+            )
+        };
+        let mut value = match occurrences.as_slice() {
+            [(_, line)] => format!("Played on {}", tick(line)),
+            lines => {
+                let mut value = format!("Played {} times, on:", lines.len());
+                for (_, line) in lines.iter().take(MAX_HOVER_OCCURRENCES) {
+                    value.push_str(&format!("\n- {}", tick(line)));
+                }
+                if lines.len() > MAX_HOVER_OCCURRENCES {
+                    value.push_str("\n- ...");
+                }
+                value
+            }
+        };
+
+        if script.lines[..=first_index]
+            .iter()
+            .any(|line| !line.waits.is_empty())
+        {
+            value.push_str("\n\nThe time spent waiting before this line is not counted");
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(Range::new(
+                char_position(text, first.span.start),
+                char_position(text, first.span.end),
+            )),
+        })
+    }
+
+    /// Finds where the macro, label or variable under the cursor is defined
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let text = self.documents.get(uri)?;
+        let script = parse(uri, text).ok()?;
+
+        let name = word_at(text, char_offset(text, position.position))?;
+        let path = uri.to_file_path().ok();
+        let definition = find_definition(&script.statements, text, path.as_deref(), &name)?;
+
+        let uri = match definition.file {
+            Some(file) if Some(file) != path.as_deref() => Url::from_file_path(file).ok()?,
+            _ => uri.clone(),
+        };
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            definition.range,
+        )))
+    }
+
+    /// Reformats the whole document
+    fn format(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let uri = &params.text_document.uri;
+        let text = self.documents.get(uri)?;
+        let formatted = parse(uri, text).ok()?.to_string();
+
+        if formatted == *text {
+            return Some(vec![]);
+        }
+
+        let end = char_position(text, text.chars().count());
+        Some(vec![TextEdit::new(
+            Range::new(Position::new(0, 0), end),
+            formatted,
+        )])
+    }
+}
+
+/// Converts a script error to a diagnostic of the document at `path`.
+/// Errors in other files are shown at the start of the document.
+fn diagnostic(error: &ScriptError, text: &str, path: Option<&Path>) -> Diagnostic {
+    let in_document = match (&error.file, path) {
+        (Some(file), Some(path)) => *file == path.display().to_string(),
+        (None, _) => true,
+        (Some(_), None) => false,
+    };
+
+    let mut message = error.message.clone();
+    let range = if in_document {
+        Range::new(
+            byte_position(text, error.span.start),
+            byte_position(text, error.span.end),
+        )
+    } else {
+        let file = error.file.as_deref().unwrap_or_default();
+        message = format!("{file}:{}:{}: {message}", error.line, error.column);
+        Range::default()
+    };
+
+    if let Some(hint) = &error.hint {
+        message.push_str(&format!("\nHint: {hint}"));
+    }
+    for context in &error.context {
+        message.push_str(&format!("\n{context}"));
+    }
+
+    Diagnostic {
+        range,
+        severity: Some(match error.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        source: Some("witas".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+struct Definition<'a> {
+    /// The file the definition is in, `None` for the document itself
+    file: Option<&'a Path>,
+    range: Range,
+}
+
+/// Looks for the definition of `name` in `statements`, written in `src`,
+/// and in the files they include.
+fn find_definition<'a>(
+    statements: &'a [Statement],
+    src: &str,
+    file: Option<&'a Path>,
+    name: &str,
+) -> Option<Definition<'a>> {
+    let found = |span: &std::ops::Range<usize>| Definition {
+        file,
+        range: Range::new(char_position(src, span.start), char_position(src, span.end)),
+    };
+
+    for statement in statements {
+        let definition = match statement {
+            Statement::Macro {
+                name: macro_name,
+                span,
+                ..
+            }
+            | Statement::Label {
+                name: macro_name,
+                span,
+            }
+            | Statement::Let {
+                name: macro_name,
+                span,
+                ..
+            } if macro_name == name => Some(found(span)),
+            Statement::Macro { body, .. } | Statement::Repeat { body, .. } => {
+                find_definition(body, src, file, name)
+            }
+            Statement::Include {
+                file: Some(included),
+                ..
+            } => find_definition(
+                &included.statements,
+                &included.src,
+                Some(&included.path),
+                name,
+            ),
+            Statement::Include { path, .. } => {
+                warn!("Included file {path} was not loaded");
+                None
+            }
+            _ => None,
+        };
+
+        if definition.is_some() {
+            return definition;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::thread::JoinHandle;
+
+    use common::tas::to_time;
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use lsp_types::notification::{
+        DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized, Notification as _,
+        PublishDiagnostics,
+    };
+    use lsp_types::request::{Formatting, GotoDefinition, HoverRequest, Initialize, Shutdown};
+    use lsp_types::{
+        DiagnosticSeverity, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+        DocumentFormattingParams, FormattingOptions, GotoDefinitionParams, GotoDefinitionResponse,
+        HoverContents, HoverParams, InitializeParams, InitializedParams, NumberOrString, Position,
+        PublishDiagnosticsParams, Range, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+        TextDocumentItem, TextDocumentPositionParams, Url, VersionedTextDocumentIdentifier,
+    };
+
+    use super::run;
+
+    /// Path of a test document, which does not need to exist
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join("witas_lsp").join(name)
+    }
+
+    /// An editor talking to a server running in another thread
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<super::Result<()>>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (server, connection) = Connection::memory();
+            let server = std::thread::spawn(move || run(server));
+
+            let mut client = Client {
+                connection,
+                server,
+                next_id: 0,
+            };
+            client.request::<Initialize>(InitializeParams::default());
+            client.notify::<Initialized>(InitializedParams {});
+
+            client
+        }
+
+        fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let request = Request::new(id.clone(), R::METHOD.to_string(), params);
+            self.connection.sender.send(request.into()).unwrap();
+
+            loop {
+                match self.connection.receiver.recv().unwrap() {
+                    Message::Response(response) if response.id == id => {
+                        let result = response.result.unwrap_or_default();
+                        return serde_json::from_value(result).unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.to_string(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        fn diagnostics(&self) -> PublishDiagnosticsParams {
+            loop {
+                if let Message::Notification(notification) =
+                    self.connection.receiver.recv().unwrap()
+                {
+                    if notification.method == PublishDiagnostics::METHOD {
+                        return serde_json::from_value(notification.params).unwrap();
+                    }
+                }
+            }
+        }
+
+        /// Opens a document and returns its diagnostics
+        fn open(&self, uri: &Url, text: &str) -> PublishDiagnosticsParams {
+            self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    uri.clone(),
+                    "wtas".to_string(),
+                    1,
+                    text.to_string(),
+                ),
+            });
+            self.diagnostics()
+        }
+
+        fn position(uri: &Url, line: u32, character: u32) -> TextDocumentPositionParams {
+            TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(line, character),
+            )
+        }
+
+        fn stop(mut self) {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            self.server.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let client = Client::start();
+        let uri = Url::from_file_path(test_path("diagnostics.wtas")).unwrap();
+
+        let diagnostics = client.open(&uri, "version 1\nstart now\n1>U\n+5>UX\n");
+        assert_eq!(diagnostics.uri, uri);
+        assert_eq!(diagnostics.diagnostics.len(), 1);
+        let diagnostic = &diagnostics.diagnostics[0];
+        assert_eq!(
+            diagnostic.range,
+            Range::new(Position::new(3, 4), Position::new(3, 5))
+        );
+        assert!(diagnostic.message.starts_with("Unknown key `X`"));
+
+        let change = |version, text: &str| {
+            client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: text.to_string(),
+                }],
+            });
+            client.diagnostics().diagnostics
+        };
+        assert_eq!(change(2, "version 1\nstart now\n1>U\n+5>u\n"), vec![]);
+
+        // Lint warnings are shown once the script parses
+        let diagnostics = change(3, "version 1\nstart now\n1>U\n+5>U\n+1>u\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("redundant-key".to_string()))
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(3, 0));
+
+        client.stop();
+    }
+
+    #[test]
+    fn test_invalid_notification() {
+        let client = Client::start();
+        let notification = Notification::new(
+            DidOpenTextDocument::METHOD.to_string(),
+            serde_json::json!({ "text": 1 }),
+        );
+        client.connection.sender.send(notification.into()).unwrap();
+
+        // The server drops the notification and keeps going
+        let uri = Url::from_file_path(test_path("invalid.wtas")).unwrap();
+        let diagnostics = client.open(&uri, "version 1\nstart now\n1>U\n+5>u\n");
+        assert_eq!(diagnostics.uri, uri);
+        assert_eq!(diagnostics.diagnostics, vec![]);
+
+        client.stop();
+    }
+
+    #[test]
+    fn test_hover() {
+        let mut client = Client::start();
+        let uri = Url::from_file_path(test_path("hover.wtas")).unwrap();
+        let src = "version 1\nstart now\n10>U\n+50>u\nrepeat 2 {\n    +5>P\n}\n";
+        assert_eq!(client.open(&uri, src).diagnostics, vec![]);
+
+        let hover = |client: &mut Client, line, character| {
+            let hover = client.request::<HoverRequest>(HoverParams {
+                text_document_position_params: Client::position(&uri, line, character),
+                work_done_progress_params: Default::default(),
+            });
+            hover.map(|hover| match hover.contents {
+                HoverContents::Markup(content) => content.value,
+                _ => panic!("Expected markdown"),
+            })
+        };
+
+        assert_eq!(
+            hover(&mut client, 3, 2),
+            Some(format!("Played on tick 60 ({})", to_time(60)))
+        );
+        assert_eq!(
+            hover(&mut client, 5, 6),
+            Some(format!(
+                "Played 2 times, on:\n- tick 65 ({})\n- tick 70 ({})",
+                to_time(65),
+                to_time(70)
+            ))
+        );
+        assert_eq!(hover(&mut client, 1, 2), None);
+
+        client.stop();
+    }
+
+    #[test]
+    fn test_definition() {
+        let mut client = Client::start();
+        let uri = Url::from_file_path(test_path("definition.wtas")).unwrap();
+        let src = "version 1\nstart now\nmacro look(dx) {\n    +5>|dx 0\n    +1>|0 0\n}\n@label bunker\n1>U\nlet turn = 300\nlook(turn)\n";
+        assert_eq!(client.open(&uri, src).diagnostics, vec![]);
+
+        let mut definition = |line, character| {
+            let response = client.request::<GotoDefinition>(GotoDefinitionParams {
+                text_document_position_params: Client::position(&uri, line, character),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            });
+            match response {
+                Some(GotoDefinitionResponse::Scalar(location)) => {
+                    assert_eq!(location.uri, uri);
+                    Some(location.range.start)
+                }
+                None => None,
+                _ => panic!("Expected a single location"),
+            }
+        };
+
+        assert_eq!(definition(9, 1), Some(Position::new(2, 0)));
+        assert_eq!(definition(9, 6), Some(Position::new(8, 0)));
+        assert_eq!(definition(6, 9), Some(Position::new(6, 0)));
+        assert_eq!(definition(7, 2), None);
+
+        client.stop();
+    }
+
+    #[test]
+    fn test_formatting() {
+        let mut client = Client::start();
+        let uri = Url::from_file_path(test_path("formatting.wtas")).unwrap();
+        let src = "version 1\nstart now\n10>U|  1   2\n+5>u|0 0\n";
+        assert_eq!(client.open(&uri, src).diagnostics, vec![]);
+
+        let mut format = || {
+            client
+                .request::<Formatting>(DocumentFormattingParams {
+                    text_document: TextDocumentIdentifier::new(uri.clone()),
+                    options: FormattingOptions::default(),
+                    work_done_progress_params: Default::default(),
+                })
+                .unwrap()
+        };
+
+        let edits = format();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(0, 0), Position::new(4, 0))
+        );
+        assert_eq!(
+            edits[0].new_text,
+            "version 1\nstart now\n10>U|1 2\n+5>u|0 0\n"
+        );
+
+        client.stop();
+    }
+}